@@ -0,0 +1,76 @@
+//! Compares the quantised and full precision fitness models: scoring speed,
+//! and how many letters of short substitution ciphertexts are recovered.
+//!
+//! Run with `cargo run --release --example fitness_bench`.
+
+use std::time::Instant;
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crack_ciphers::fitness::{Precision, compute_fitness, default_fitness_matrix};
+use crack_ciphers::search::SearchOptions;
+use crack_ciphers::substitution;
+
+const SAMPLES: [&str; 4] = [
+    "it was the best of times it was the worst of times it was the age of wisdom \
+     it was the age of foolishness it was the epoch of belief",
+    "all happy families are alike each unhappy family is unhappy in its own way \
+     everything was in confusion in the house",
+    "call me ishmael some years ago never mind how long precisely having little \
+     or no money in my purse and nothing particular to interest me on shore",
+    "it is a truth universally acknowledged that a single man in possession of a \
+     good fortune must be in want of a wife",
+];
+
+const PRECISIONS: [Precision; 5] = [
+    Precision::Quantised(1),
+    Precision::Quantised(2),
+    Precision::Quantised(4),
+    Precision::Quantised(8),
+    Precision::Full,
+];
+
+fn main() {
+    // Ciphertext is cracked without its word breaks, as the models have none.
+    let samples: Vec<String> = SAMPLES.iter()
+        .map(|sample| sample.chars().filter(|x| *x != ' ').collect())
+        .collect();
+    let text: Vec<u8> = samples.concat().bytes().map(|x| x - b'a').collect();
+    println!("{:>10} {:>14} {:>10}", "precision", "ns per score", "correct");
+    for precision in PRECISIONS {
+        let matrix = default_fitness_matrix(4, precision).unwrap();
+
+        let rounds = 20000;
+        let start = Instant::now();
+        let mut total = 0.0;
+        for _ in 0..rounds {
+            total += compute_fitness(&text, &matrix);
+        }
+        let per_score = start.elapsed().as_nanos() as f64 / rounds as f64;
+        assert!(total > 0.0);
+
+        let mut correct = 0;
+        let mut letters = 0;
+        for (seed, sample) in samples.iter().enumerate() {
+            let key = substitution::generate_key(&mut StdRng::seed_from_u64(seed as u64));
+            let ciphertext = substitution::encrypt(sample, &key).unwrap();
+            let options = SearchOptions {
+                max_restarts: 300,
+                report_every: 0,
+                seed: seed as u64,
+                ..substitution::search_options()
+            };
+            let result = substitution::crack(&ciphertext, &matrix, &options).unwrap();
+            let plaintext = substitution::decrypt(&ciphertext, &result.key).unwrap();
+            correct += plaintext.chars().zip(sample.chars()).filter(|(x, y)| x == y).count();
+            letters += sample.len();
+        }
+        let name = match precision {
+            Precision::Quantised(bits) => format!("{bits} bits"),
+            Precision::Full => "full".to_string(),
+        };
+        println!("{:>10} {:>14.0} {:>9.1}%", name, per_score,
+            correct as f64 * 100.0 / letters as f64);
+    }
+}
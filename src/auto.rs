@@ -0,0 +1,142 @@
+use std::time::{Duration, Instant};
+
+use crate::caesar::{self, Caesar};
+use crate::cipher::{Cipher, reverse_permutation};
+use crate::fitness::{FitnessMatrix, compute_fitness};
+use crate::identify::{self, Family};
+use crate::normalise::Normaliser;
+use crate::search::SearchOptions;
+use crate::stats::to_letters;
+use crate::substitution::{self, Substitution};
+use crate::vigenere;
+
+/// Families less likely than this are only tried when no likelier one can be
+/// cracked.
+const MIN_CONFIDENCE: f64 = 0.01;
+
+/// A decryption found by [`solve`].
+#[derive(Clone, Debug)]
+pub struct Solution {
+    pub family: Family,
+    pub key: String,
+    /// Letters of the plaintext, `a..=z` only.
+    pub plaintext: String,
+    /// Fitness of `plaintext`, comparable between families.
+    pub fitness: f64,
+}
+
+/// Families [`solve`] has a cracker for.
+pub fn can_crack(family: Family) -> bool {
+    matches!(family, Family::Caesar | Family::Vigenere | Family::Substitution)
+}
+
+/// Rank of the key space of each family [`solve`] cracks, smallest first,
+/// which decides between solutions of equal fitness.
+fn key_space_rank(family: Family) -> usize {
+    match family {
+        Family::Caesar => 0,
+        Family::Vigenere => 1,
+        _ => 2,
+    }
+}
+
+/// Cracks `ciphertext` as `family`, giving up on new restarts at
+/// `options.deadline`.
+pub fn crack(family: Family, ciphertext: &str, matrix: &FitnessMatrix, options: &SearchOptions)
+    -> Result<Solution, String>
+{
+    let letters = Normaliser::ciphertext(false).normalise(ciphertext);
+    let (key, plaintext) = match family {
+        Family::Caesar => {
+            let (_, key) = caesar::crack(&letters, matrix.unigram())?[0];
+            (Caesar.format_key(&key), caesar::decrypt(&letters, key)?)
+        },
+        Family::Vigenere => {
+            // Solving the columns is quick, random restarts are the fallback.
+            let report = options.report_every != 0;
+            let result = match vigenere::crack_columns(&letters, matrix, true, report) {
+                Ok(result) => result,
+                Err(_) => vigenere::crack(&letters, matrix,
+                    &with_settings(vigenere::search_options(), options))?,
+            };
+            let plaintext = vigenere::decrypt(&letters, &result.key)?;
+            (result.key, plaintext)
+        },
+        Family::Substitution => {
+            let options = with_settings(substitution::search_options(), options);
+            let result = substitution::crack(&letters, matrix, &options)?;
+            let plaintext = substitution::decrypt(&letters, &result.key)?;
+            (Substitution.format_key(&reverse_permutation(&result.key)), plaintext)
+        },
+        _ => return Err(format!("No cracker for {}", family.name())),
+    };
+    let fitness = compute_fitness(&to_letters(&plaintext), matrix);
+    Ok(Solution { family, key, plaintext, fitness })
+}
+
+/// `defaults` of a cracker with the strategies, threads, seed and deadline
+/// of `settings`.
+fn with_settings(defaults: SearchOptions, settings: &SearchOptions) -> SearchOptions {
+    SearchOptions {
+        strategies: settings.strategies.clone(),
+        threads: settings.threads,
+        seed: settings.seed,
+        deadline: settings.deadline,
+        ..defaults
+    }
+}
+
+/// Identifies `ciphertext` and cracks it as each likely family in turn,
+/// sharing `budget` between them by confidence. Strategies, threads and seed
+/// come from `options`, and progress is reported on stderr if
+/// `options.report_every` is set. Returns the solutions found, best first,
+/// the family with the smaller key space first on equal fitness.
+pub fn solve(ciphertext: &str, matrix: &FitnessMatrix, options: &SearchOptions, budget: Duration)
+    -> Result<Vec<Solution>, String>
+{
+    let (_, ranked) = identify::identify(ciphertext)?;
+    let mut candidates: Vec<(Family, f64)> = ranked.iter()
+        .filter(|(family, confidence)| can_crack(*family) && *confidence >= MIN_CONFIDENCE)
+        .copied()
+        .collect();
+    if candidates.is_empty() {
+        candidates = ranked.into_iter().filter(|(family, _)| can_crack(*family)).collect();
+    }
+
+    let report = options.report_every != 0;
+    let end = Instant::now() + budget;
+    let mut solutions = Vec::new();
+    for idx in 0..candidates.len() {
+        let (family, confidence) = candidates[idx];
+        let now = Instant::now();
+        if now >= end {
+            if report {
+                eprintln!("Out of time before {}", family.name());
+            }
+            break;
+        }
+        // Each family gets its share of the time left, and at least a tenth.
+        let remaining: f64 = candidates[idx..].iter().map(|(_, confidence)| confidence).sum();
+        let share = (confidence / remaining).max(0.1);
+        let deadline = now + (end - now).mul_f64(share.min(1.0));
+        if report {
+            eprintln!("Trying {} ({:.1}%)", family.name(), confidence * 100.0);
+        }
+        let options = SearchOptions { deadline: Some(deadline), ..options.clone() };
+        match crack(family, ciphertext, matrix, &options) {
+            Ok(solution) => {
+                if report {
+                    eprintln!("{}: fitness {:.4}, key {}", family.name(), solution.fitness,
+                        solution.key);
+                }
+                solutions.push(solution);
+            },
+            Err(e) => if report {
+                eprintln!("{}: {e}", family.name());
+            },
+        }
+    }
+    solutions.sort_by(|s1, s2| s2.fitness.partial_cmp(&s1.fitness).unwrap()
+        .then(key_space_rank(s1.family).cmp(&key_space_rank(s2.family))));
+    Ok(solutions)
+}
@@ -0,0 +1,163 @@
+use std::time::Instant;
+
+use rand::Rng;
+
+use crate::cipher::Cipher;
+use crate::english::ALPHABET;
+use crate::fitness::{FitnessMatrix, compute_fitness};
+use crate::search::{Neighbourhood, Scorer, SearchOptions, SearchResult, search};
+use crate::vigenere;
+
+/// Autokey cipher: Vigenère whose key is a primer followed by the
+/// plaintext itself. `key_length` is the length of generated primers.
+pub struct Autokey {
+    pub key_length: usize,
+}
+
+/// Longest primer [`crack`] tries.
+pub const MAX_PRIMER_LENGTH: usize = 20;
+
+fn validate(text: &str, primer: &str) -> Result<(), String> {
+    if primer.is_empty() {
+        return Err("Primer must not be empty".to_string());
+    }
+    if let Some(invalid) = text.chars().chain(primer.chars()).find(|x| !x.is_ascii_lowercase()) {
+        return Err(format!("Char '{invalid}' is out of range a..=z"));
+    }
+    Ok(())
+}
+
+pub fn encrypt(plaintext: &str, primer: &str) -> Result<String, String> {
+    validate(plaintext, primer)?;
+    let key: String = primer.chars().chain(plaintext.chars()).take(plaintext.len()).collect();
+    vigenere::encrypt(plaintext, &key)
+}
+
+/// Decrypts letter by letter, as each plaintext letter is the key of the
+/// letter a primer length later.
+pub fn decrypt(ciphertext: &str, primer: &str) -> Result<String, String> {
+    validate(ciphertext, primer)?;
+    let ciphertext: Vec<u8> = ciphertext.bytes().map(|x| x - b'a').collect();
+    let primer: Vec<u8> = primer.bytes().map(|x| x - b'a').collect();
+    Ok(decrypt_letters(&ciphertext, &primer).into_iter()
+        .map(|x| ALPHABET[x as usize])
+        .collect())
+}
+
+fn decrypt_letters(ciphertext: &[u8], primer: &[u8]) -> Vec<u8> {
+    let mut plaintext: Vec<u8> = Vec::with_capacity(ciphertext.len());
+    for (idx, x) in ciphertext.iter().enumerate() {
+        let key = if idx < primer.len() { primer[idx] } else { plaintext[idx - primer.len()] };
+        plaintext.push((x + 26 - key) % 26);
+    }
+    plaintext
+}
+
+/// Searches primers of a fixed length by changing one letter at a time.
+pub struct AutokeyCracker<'a> {
+    ciphertext: Vec<u8>,
+    primer_length: usize,
+    matrix: &'a FitnessMatrix,
+}
+
+impl<'a> AutokeyCracker<'a> {
+    pub fn new(ciphertext: &str, primer_length: usize, matrix: &'a FitnessMatrix) -> Self {
+        let ciphertext = ciphertext.bytes().map(|x| x - b'a').collect();
+        AutokeyCracker { ciphertext, primer_length, matrix }
+    }
+}
+
+impl Neighbourhood for AutokeyCracker<'_> {
+    type Key = String;
+
+    fn random_key<R: Rng + ?Sized>(&self, rng: &mut R) -> String {
+        vigenere::generate_key(self.primer_length, rng)
+    }
+
+    fn neighbour_count(&self) -> usize {
+        self.primer_length * 26
+    }
+
+    fn neighbour(&self, key: &String, idx: usize) -> String {
+        let mut key: Vec<char> = key.chars().collect();
+        key[idx / 26] = ALPHABET[idx % 26];
+        key.into_iter().collect()
+    }
+}
+
+impl Scorer<String> for AutokeyCracker<'_> {
+    fn score(&self, key: &String) -> f64 {
+        let primer: Vec<u8> = key.bytes().map(|x| x - b'a').collect();
+        compute_fitness(&decrypt_letters(&self.ciphertext, &primer), self.matrix)
+    }
+}
+
+/// Default search settings for [`crack`], applied to every primer length.
+pub fn search_options() -> SearchOptions {
+    SearchOptions {
+        max_restarts: 4,
+        max_hits: 2,
+        ..SearchOptions::default()
+    }
+}
+
+/// Searches every primer length up to [`MAX_PRIMER_LENGTH`] and keeps the
+/// fittest primer, the shorter one on ties, reporting each length tried if
+/// `options.report_every` is set.
+pub fn crack(ciphertext: &str, matrix: &FitnessMatrix, options: &SearchOptions)
+    -> Result<SearchResult<String>, &'static str>
+{
+    if ciphertext.len() < 20 {
+        return Err("Length of cipher must be at least 20");
+    }
+    if !ciphertext.chars().all(|x| x.is_ascii_lowercase()) {
+        return Err("Cipher must only contain a..=z");
+    }
+    let mut best: Option<SearchResult<String>> = None;
+    for length in 1..=MAX_PRIMER_LENGTH.min(ciphertext.len() / 4) {
+        if best.is_some() && options.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            break;
+        }
+        let cracker = AutokeyCracker::new(ciphertext, length, matrix);
+        let result = search(&cracker, options);
+        if options.report_every != 0 {
+            eprintln!("Length: {length}, primer {}, fitness {:.4}", result.key, result.fitness);
+        }
+        if best.as_ref().is_none_or(|current| result.fitness > current.fitness) {
+            best = Some(result);
+        }
+    }
+    best.ok_or("No primer length to try")
+}
+
+impl Cipher for Autokey {
+    type Key = String;
+
+    fn name(&self) -> &'static str {
+        "autokey"
+    }
+
+    fn encrypt(&self, plaintext: &str, key: &String) -> Result<String, String> {
+        encrypt(plaintext, key)
+    }
+
+    fn decrypt(&self, ciphertext: &str, key: &String) -> Result<String, String> {
+        decrypt(ciphertext, key)
+    }
+
+    fn parse_key(&self, key: &str) -> Result<String, String> {
+        let key = key.trim();
+        if key.is_empty() || !key.chars().all(|x| x.is_ascii_lowercase()) {
+            return Err(format!("Primer '{key}' must be letters a..=z"));
+        }
+        Ok(key.to_string())
+    }
+
+    fn format_key(&self, key: &String) -> String {
+        key.clone()
+    }
+
+    fn generate_key<R: Rng + ?Sized>(&self, rng: &mut R) -> String {
+        vigenere::generate_key(self.key_length, rng)
+    }
+}
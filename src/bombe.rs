@@ -0,0 +1,477 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use crate::enigma::{self, CrackOptions, WheelTables};
+use crate::enigma_machine::{KeySheet, Machine, Plugboard};
+use crate::fitness::{FitnessMatrix, compute_fitness};
+
+/// Every wire of a letter of the bombe's test register live.
+const ALL_LIVE: u32 = (1 << 26) - 1;
+
+/// A crib placed against the ciphertext, as drawn for the bombe: letters
+/// joined by the crib positions at which one enciphers to the other.
+#[derive(Clone, Debug)]
+pub struct Menu {
+    /// Position of the crib in the ciphertext.
+    pub offset: usize,
+    /// Plaintext and ciphertext letters (0..26) at each position of the crib.
+    pub pairs: Vec<(u8, u8)>,
+}
+
+impl Menu {
+    /// Places `crib` at `offset` of `ciphertext`, both `a..=z`. An Enigma
+    /// never enciphers a letter to itself, so neither may the crib.
+    pub fn new(ciphertext: &str, crib: &str, offset: usize) -> Result<Menu, String> {
+        if !ciphertext.chars().chain(crib.chars()).all(|x| x.is_ascii_lowercase()) {
+            return Err("Ciphertext and crib must only contain a..=z".to_string());
+        }
+        let end = offset.checked_add(crib.len());
+        if crib.is_empty() || end.is_none_or(|end| end > ciphertext.len()) {
+            return Err(format!("Crib of {} letters does not fit at {offset}", crib.len()));
+        }
+        let pairs: Vec<(u8, u8)> = crib.bytes()
+            .zip(ciphertext[offset..].bytes())
+            .map(|(p, c)| (p - b'a', c - b'a'))
+            .collect();
+        if let Some(idx) = pairs.iter().position(|(p, c)| p == c) {
+            return Err(format!("Crib letter {} would encipher to itself at {}",
+                crib.as_bytes()[idx] as char, offset + idx));
+        }
+        Ok(Menu { offset, pairs })
+    }
+
+    /// Letters joined to each letter, with the crib position joining them.
+    fn links(&self) -> Vec<Vec<(u8, usize)>> {
+        let mut links = vec![Vec::new(); 26];
+        for (idx, (p, c)) in self.pairs.iter().enumerate() {
+            links[*p as usize].push((*c, idx));
+            links[*c as usize].push((*p, idx));
+        }
+        links
+    }
+
+    /// Letters of the menu.
+    pub fn letters(&self) -> Vec<u8> {
+        let links = self.links();
+        (0..26).filter(|x| !links[*x as usize].is_empty()).collect()
+    }
+
+    /// The letter with the most links, where the bombe puts its test current.
+    pub fn test_letter(&self) -> u8 {
+        let links = self.links();
+        (0..26u8).max_by_key(|x| (links[*x as usize].len(), 26 - x)).unwrap()
+    }
+
+    /// A loop closed by each link that is not needed to connect the letters,
+    /// as the crib positions around it. Loops are what stop a bombe at few
+    /// wrong positions: without any, nearly every position stops it.
+    pub fn loops(&self) -> Vec<Vec<usize>> {
+        let links = self.links();
+        // Breadth first spanning forest: the link to the parent and depth of
+        // each letter.
+        let mut parent: [Option<(u8, usize)>; 26] = [None; 26];
+        let mut depth = [usize::MAX; 26];
+        let mut in_tree = vec![false; self.pairs.len()];
+        for root in self.letters() {
+            if depth[root as usize] != usize::MAX {
+                continue;
+            }
+            depth[root as usize] = 0;
+            let mut queue = VecDeque::from([root]);
+            while let Some(letter) = queue.pop_front() {
+                for (other, idx) in &links[letter as usize] {
+                    if depth[*other as usize] == usize::MAX {
+                        depth[*other as usize] = depth[letter as usize] + 1;
+                        parent[*other as usize] = Some((letter, *idx));
+                        in_tree[*idx] = true;
+                        queue.push_back(*other);
+                    }
+                }
+            }
+        }
+        (0..self.pairs.len())
+            .filter(|idx| !in_tree[*idx])
+            .map(|idx| {
+                // Climb from both ends to where the tree paths meet.
+                let (mut a, mut b) = self.pairs[idx];
+                let (mut up_a, mut up_b) = (Vec::new(), Vec::new());
+                while a != b {
+                    if depth[a as usize] >= depth[b as usize] {
+                        let (next, link) = parent[a as usize].unwrap();
+                        up_a.push(link);
+                        a = next;
+                    } else {
+                        let (next, link) = parent[b as usize].unwrap();
+                        up_b.push(link);
+                        b = next;
+                    }
+                }
+                let mut positions = vec![idx];
+                positions.extend(up_b);
+                positions.extend(up_a.into_iter().rev());
+                positions
+            })
+            .collect()
+    }
+}
+
+/// Offsets of `ciphertext` at which `crib` may stand: those where no crib
+/// letter meets the same ciphertext letter, which an Enigma cannot give.
+pub fn crib_positions(ciphertext: &str, crib: &str) -> Vec<usize> {
+    if crib.is_empty() || crib.len() > ciphertext.len() {
+        return Vec::new();
+    }
+    (0..=ciphertext.len() - crib.len())
+        .filter(|offset| {
+            crib.bytes().zip(ciphertext[*offset..].bytes()).all(|(p, c)| p != c)
+        })
+        .collect()
+}
+
+/// The bombe's scramblers for one wheel order: `maps[state * 26 + right]`
+/// is the Enigma without plugs with the left and middle wheels at `state`,
+/// `left * 26 + middle`, and the right one at `right`. Along the crib the
+/// right wheel moves on by one a letter and the others are at `states`.
+struct Scramblers<'a> {
+    links: &'a [Vec<(u8, usize)>],
+    maps: Vec<[u8; 26]>,
+    states: Vec<usize>,
+    right: usize,
+}
+
+impl Scramblers<'_> {
+    fn map(&self, idx: usize, x: u8) -> u8 {
+        self.maps[self.states[idx] * 26 + (self.right + idx + 1) % 26][x as usize]
+    }
+
+    /// Spreads the current from `wire` of `letter` through the scramblers and
+    /// the diagonal board: bit `u` of `live[a]` ends up set for every `a`
+    /// steckered to `u` that follows from `letter` being steckered to `wire`.
+    /// Gives up once every wire of `test` is live.
+    fn spread(&self, live: &mut [u32; 26], letter: u8, wire: u8, test: u8,
+        stack: &mut Vec<(u8, u8)>)
+    {
+        stack.clear();
+        stack.push((letter, wire));
+        while let Some((a, u)) = stack.pop() {
+            if live[a as usize] & 1 << u != 0 {
+                continue;
+            }
+            live[a as usize] |= 1 << u;
+            if live[test as usize] == ALL_LIVE {
+                return;
+            }
+            stack.push((u, a));
+            for (b, idx) in &self.links[a as usize] {
+                stack.push((*b, self.map(*idx, u)));
+            }
+        }
+    }
+}
+
+/// Runs `menu` on a bombe over every wheel order of `options` and every
+/// position, and checks each stop as the checking machine did: the plugs it
+/// implies must pair letters one to one. Returns the keys of the stops,
+/// positions being those at the start of the crib and plugs those deduced.
+///
+/// The right ring only moves the turnovers, so each position is tried with
+/// the middle wheel turning over at each letter of the crib, or not at all,
+/// and the right ring of a stop is one that puts the turnover there. Each is
+/// tried again with the middle wheel double stepping where it can, and the
+/// middle ring of a stop is one that makes it double step there or not at
+/// all.
+pub fn run(menu: &Menu, options: &CrackOptions) -> Vec<KeySheet> {
+    let orders = enigma::wheel_orders(options);
+    let links = menu.links();
+    let test = menu.test_letter();
+    let next_order = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let found: Mutex<Vec<(usize, KeySheet)>> = Mutex::new(Vec::new());
+
+    let worker = || {
+        let mut stops = Vec::new();
+        let mut stack = Vec::new();
+        loop {
+            let idx = next_order.fetch_add(1, Ordering::Relaxed);
+            let Some((reflector, rotors)) = orders.get(idx) else {
+                break;
+            };
+            let key = KeySheet {
+                reflector: *reflector,
+                rotors: *rotors,
+                rings: [0; 3],
+                positions: [0; 3],
+                plugboard: Plugboard::default(),
+            };
+            let tables = WheelTables::new(&key);
+            let mut scramblers = Scramblers {
+                links: &links,
+                maps: (0..676 * 26)
+                    .map(|x| std::array::from_fn(|y| tables.map(x / 26, x % 26, y as u8)))
+                    .collect(),
+                states: Vec::new(),
+                right: 0,
+            };
+            for start in 0..676 {
+                // The states along the crib depend on where the right wheel
+                // starts, counting the ring, and many starts share them. The
+                // middle ring decides whether the middle wheel double steps,
+                // which it can at the first letter or the one after it turns
+                // over, taking the left wheel along.
+                let mut turnovers: Vec<(usize, Vec<usize>)> = Vec::new();
+                for window in 0..26 {
+                    let double_steps = (0..menu.pairs.len())
+                        .filter(|pos| *pos == 0 || tables.turns_middle((window + pos - 1) % 26));
+                    for double_step in [None].into_iter().chain(double_steps.map(Some)) {
+                        let states: Vec<usize> = (0..menu.pairs.len())
+                            .scan(start, |state, pos| {
+                                if double_step == Some(pos) {
+                                    *state = (*state / 26 + 1) % 26 * 26 + (*state + 1) % 26;
+                                } else if tables.turns_middle((window + pos) % 26) {
+                                    *state = *state / 26 * 26 + (*state + 1) % 26;
+                                }
+                                Some(*state)
+                            })
+                            .collect();
+                        if turnovers.iter().all(|(_, other)| *other != states) {
+                            turnovers.push((window, states));
+                        }
+                    }
+                }
+                for (window, states) in turnovers {
+                    scramblers.states = states;
+                    for right in 0..26 {
+                        scramblers.right = right;
+                        let mut live = [0; 26];
+                        scramblers.spread(&mut live, test, 0, test, &mut stack);
+                        let lit = live[test as usize];
+                        if lit == ALL_LIVE {
+                            continue;
+                        }
+                        // Right guesses light up nothing else, so the stecker
+                        // of the test letter is an unlit wire, or any wire
+                        // when the input one lit nothing else.
+                        let guesses: Vec<u8> = (0..26)
+                            .filter(|u| lit == 1 || lit & 1 << u == 0)
+                            .collect();
+                        for guess in guesses {
+                            let mut live = [0; 26];
+                            scramblers.spread(&mut live, test, guess, test, &mut stack);
+                            if live.iter().any(|wires| wires.count_ones() > 1) {
+                                continue;
+                            }
+                            let path: Vec<[u8; 3]> = scramblers.states.iter()
+                                .enumerate()
+                                .map(|(idx, state)| [(state / 26) as u8, (state % 26) as u8,
+                                    ((right + idx + 1) % 26) as u8])
+                                .collect();
+                            // A middle ring that double steps the middle wheel
+                            // where the bombe did, if at all.
+                            let Some(mut stop) = (0..26u8)
+                                .map(|ring| KeySheet {
+                                    rings: [0, ring, ((window + 26 - right) % 26) as u8],
+                                    positions: [(start / 26) as u8,
+                                        ((start % 26) as u8 + ring) % 26, window as u8],
+                                    ..key.clone()
+                                })
+                                .find(|stop| core_path(stop, path.len()) == path) else {
+                                continue;
+                            };
+                            stop.plugboard = Plugboard((0..26u8)
+                                .flat_map(|a| (a + 1..26).map(move |u| (a, u)))
+                                .filter(|(a, u)| live[*a as usize] & 1 << u != 0)
+                                .collect());
+                            let found = ((idx * 676 + start) * 26 + right) * 26 + window;
+                            stops.push((found, stop));
+                        }
+                    }
+                }
+            }
+            let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+            if options.report {
+                eprintln!("{done}/{} wheel orders tried, {} stops", orders.len(), stops.len());
+            }
+        }
+        found.lock().unwrap().append(&mut stops);
+    };
+    thread::scope(|scope| {
+        for _ in 1..options.threads {
+            scope.spawn(worker);
+        }
+        worker();
+    });
+
+    let mut found = found.into_inner().unwrap();
+    found.sort_by_key(|(idx, _)| *idx);
+    found.into_iter().map(|(_, key)| key).collect()
+}
+
+/// Turns the wheels of a bombe stop back from the crib to the start of the
+/// message and completes the plugboard from the deduced plugs. Returns the
+/// fitness of the decryption by `trigrams`, and the key.
+pub fn verify(ciphertext: &str, menu: &Menu, stop: &KeySheet, bigrams: &FitnessMatrix,
+    trigrams: &FitnessMatrix, max_plugs: usize) -> Result<(f64, KeySheet), &'static str>
+{
+    if !ciphertext.chars().all(|x| x.is_ascii_lowercase()) {
+        return Err("Cipher must only contain a..=z");
+    }
+    let letters: Vec<u8> = ciphertext.bytes().map(|x| x - b'a').collect();
+    let (_, at_crib) = set_rings(stop, menu.pairs.len(), &letters[menu.offset..], bigrams);
+    // Several starts may step to the same positions, the first is as good.
+    let start = (0..26 * 26 * 26)
+        .map(|start| KeySheet {
+            positions: [(start / 676) as u8, (start / 26 % 26) as u8, (start % 26) as u8],
+            ..at_crib.clone()
+        })
+        .find(|key| {
+            let mut machine = Machine::new(key);
+            for _ in 0..menu.offset {
+                machine.step();
+            }
+            machine.positions() == at_crib.positions
+        })
+        .ok_or("No start position steps to the positions at the crib")?;
+    Ok(enigma::complete_plugboard(&start, &letters, bigrams, trigrams, max_plugs))
+}
+
+/// Positions of the wheels' wiring, the windows less the rings, after each
+/// of the first `len` key presses with `key`.
+fn core_path(key: &KeySheet, len: usize) -> Vec<[u8; 3]> {
+    let mut machine = Machine::new(key);
+    (0..len)
+        .map(|_| {
+            machine.step();
+            let positions = machine.positions();
+            std::array::from_fn(|idx| (positions[idx] + 26 - key.rings[idx]) % 26)
+        })
+        .collect()
+}
+
+/// Sets the middle and right rings of `stop` to those whose decryption of
+/// `ciphertext` from the crib on scores best by `bigrams`, and returns that
+/// fitness and key. The wheels must turn as the bombe found them over the
+/// `crib_len` letters of the crib, the rings also decide the turnovers after.
+fn set_rings(stop: &KeySheet, crib_len: usize, ciphertext: &[u8], bigrams: &FitnessMatrix)
+    -> (f64, KeySheet)
+{
+    let path = core_path(stop, crib_len);
+    (0..26 * 26)
+        .map(|rings: usize| {
+            let mut key = stop.clone();
+            for (wheel, ring) in [(1, rings / 26), (2, rings % 26)] {
+                let core = (stop.positions[wheel] + 26 - stop.rings[wheel]) % 26;
+                key.rings[wheel] = ring as u8;
+                key.positions[wheel] = (core + ring as u8) % 26;
+            }
+            key
+        })
+        .filter(|key| core_path(key, crib_len) == path)
+        .map(|key| {
+            let mut machine = Machine::new(&key);
+            let plaintext: Vec<u8> = ciphertext.iter().map(|x| machine.press(*x)).collect();
+            (compute_fitness(&plaintext, bigrams), key)
+        })
+        .max_by(|a, b| a.0.partial_cmp(&b.0).unwrap())
+        .unwrap()
+}
+
+/// Whether `key` deciphers at least four in five letters of the crib, which
+/// a wrong stop refined to fit English all but never does.
+fn reads_crib(ciphertext: &str, menu: &Menu, key: &KeySheet) -> bool {
+    let mut machine = Machine::new(key);
+    let plaintext: Vec<u8> = ciphertext.bytes().map(|x| machine.press(x - b'a')).collect();
+    let matches = menu.pairs.iter()
+        .zip(&plaintext[menu.offset..])
+        .filter(|((p, _), x)| p == *x)
+        .count();
+    matches * 5 >= menu.pairs.len() * 4
+}
+
+/// Verifies the `options.candidates` stops whose decryption from the crib on
+/// scores best by `bigrams` with the deduced plugs alone, and returns the
+/// best key by [`verify`] that deciphers the crib.
+pub fn solve(ciphertext: &str, menu: &Menu, stops: &[KeySheet], bigrams: &FitnessMatrix,
+    trigrams: &FitnessMatrix, options: &CrackOptions) -> Result<(f64, KeySheet), &'static str>
+{
+    if !ciphertext.chars().all(|x| x.is_ascii_lowercase()) {
+        return Err("Cipher must only contain a..=z");
+    }
+    let letters: Vec<u8> = ciphertext.bytes().skip(menu.offset).map(|x| x - b'a').collect();
+    let mut ranked: Vec<(f64, &KeySheet)> = stops.iter()
+        .map(|stop| (set_rings(stop, menu.pairs.len(), &letters, bigrams).0, stop))
+        .collect();
+    ranked.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap());
+    ranked.truncate(options.candidates.max(1));
+    let mut best: Option<(f64, KeySheet)> = None;
+    for (idx, (_, stop)) in ranked.iter().enumerate() {
+        // Wrong stops may end at positions the wheels never reach.
+        let Ok((fitness, key)) = verify(ciphertext, menu, stop, bigrams, trigrams,
+            options.max_plugs) else {
+            continue;
+        };
+        let verified = reads_crib(ciphertext, menu, &key);
+        if options.report {
+            eprintln!("Stop {}/{}: fitness {fitness:.4}, key {}{}", idx + 1, ranked.len(),
+                key.format(), if verified { "" } else { ", crib not deciphered" });
+        }
+        if !verified {
+            continue;
+        }
+        if best.as_ref().is_none_or(|(best, _)| fitness > *best) {
+            best = Some((fitness, key));
+        }
+    }
+    best.ok_or("No stop deciphers the crib")
+}
+
+/// Runs the bombe with `crib` at each of the first `max_positions` of its
+/// [`crib_positions`] in turn, and returns the first key by [`solve`], which
+/// deciphers the crib, and the menu it came from.
+pub fn crack(ciphertext: &str, crib: &str, max_positions: usize, bigrams: &FitnessMatrix,
+    trigrams: &FitnessMatrix, options: &CrackOptions) -> Result<(f64, KeySheet, Menu), &'static str>
+{
+    if !ciphertext.chars().chain(crib.chars()).all(|x| x.is_ascii_lowercase()) {
+        return Err("Ciphertext and crib must only contain a..=z");
+    }
+    let mut positions = crib_positions(ciphertext, crib);
+    if positions.is_empty() {
+        return Err("The crib fits nowhere in the ciphertext");
+    }
+    positions.truncate(max_positions);
+    let quiet = CrackOptions { report: false, ..options.clone() };
+    for (idx, offset) in positions.iter().enumerate() {
+        let menu = Menu::new(ciphertext, crib, *offset).unwrap();
+        let stops = run(&menu, &quiet);
+        if options.report {
+            eprintln!("Position {}/{}: offset {offset}, {} loops, {} stops", idx + 1,
+                positions.len(), menu.loops().len(), stops.len());
+        }
+        if let Ok((fitness, key)) = solve(ciphertext, &menu, &stops, bigrams, trigrams, &quiet) {
+            return Ok((fitness, key, menu));
+        }
+    }
+    Err("No stop deciphers the crib")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn menu_rejects_cribs_that_do_not_fit() {
+        assert!(Menu::new("abcdef", "xyz", 3).is_ok());
+        assert!(Menu::new("abcdef", "xyz", 4).is_err());
+        assert!(Menu::new("abcdef", "xyz", usize::MAX).is_err());
+        assert!(Menu::new("abcdef", "", 0).is_err());
+    }
+
+    #[test]
+    fn crib_positions_skip_self_encipherment() {
+        assert_eq!(crib_positions("abcab", "ba"), vec![0, 3]);
+        assert_eq!(crib_positions("aaaa", "xy"), vec![0, 1, 2]);
+        assert_eq!(crib_positions("abc", "abcd"), Vec::<usize>::new());
+        assert_eq!(crib_positions("abc", ""), Vec::<usize>::new());
+    }
+}
@@ -1,29 +1,35 @@
-use crate::english::{ALPHABET, ENGLISH_FREQ};
+use rand::Rng;
 
-fn encrypt(plaintext: &str, key: usize) -> Result<String, String> {
-    if key < 1 || key > 26 {
+use crate::cipher::Cipher;
+use crate::english::ALPHABET;
+
+pub struct Caesar;
+
+/// Shifts every letter of `plaintext` back by `key` (1..=26).
+pub fn encrypt(plaintext: &str, key: usize) -> Result<String, String> {
+    if !(1..=26).contains(&key) {
         return Err(format!("Key {key} is out of range 1..=26"));
     }
     let mut ciphertext = String::new();
     for plaintext_char in plaintext.chars() {
         let from = ALPHABET.iter()
             .position(|&alphabet_char| alphabet_char == plaintext_char)
-            .expect(&format!("Char '{plaintext_char}' is out of range a..=z"));
+            .ok_or(format!("Char '{plaintext_char}' is out of range a..=z"))?;
         let to = (from+26-key) % 26;
         ciphertext.push(ALPHABET[to]);
     }
     Ok(ciphertext)
 }
 
-fn decrypt(ciphertext: &str, key: usize) -> Result<String, String> {
-    if key < 1 || key > 26 {
+pub fn decrypt(ciphertext: &str, key: usize) -> Result<String, String> {
+    if !(1..=26).contains(&key) {
         return Err(format!("Key {key} is out of range 1..=26"));
     }
     let mut plaintext = String::new();
     for ciphertext_char in ciphertext.chars() {
         let from = ALPHABET.iter()
             .position(|&alphabet_char| alphabet_char == ciphertext_char)
-            .expect(&format!("Char '{ciphertext_char}' is out of range a..=z"));
+            .ok_or(format!("Char '{ciphertext_char}' is out of range a..=z"))?;
         let to = (from+key) % 26;
         plaintext.push(ALPHABET[to]);
     }
@@ -39,7 +45,7 @@ fn counter(text: &str) -> Vec<usize> {
     counts
 }
 
-fn chi_sqr(text: &str, expect_freq: &[f64; 26]) -> f64 {
+pub fn chi_sqr(text: &str, expect_freq: &[f64; 26]) -> f64 {
     let counts = counter(text);
     let text_len = text.len() as f64;
     let chi: f64 = counts.into_iter().zip(expect_freq)
@@ -50,34 +56,43 @@ fn chi_sqr(text: &str, expect_freq: &[f64; 26]) -> f64 {
     chi
 }
 
-
-fn crack(cipher: &str, expect_freq: &[f64; 26]) -> Vec<(f64, usize)> {
-    let mut cracks: Vec<(f64, usize)> = (1..27).into_iter()
-        .map(|i| {
-            let decrypted = decrypt(cipher, i).unwrap();
-            let chi = chi_sqr(&decrypted, expect_freq);
-            (chi, i)
-        }).collect();
+/// Tries every shift and returns `(chi squared, key)` pairs, best first.
+pub fn crack(cipher: &str, expect_freq: &[f64; 26]) -> Result<Vec<(f64, usize)>, String> {
+    let mut cracks: Vec<(f64, usize)> = Vec::new();
+    for i in 1..27 {
+        let decrypted = decrypt(cipher, i)?;
+        let chi = chi_sqr(&decrypted, expect_freq);
+        cracks.push((chi, i));
+    }
     cracks.sort_by(|(c1, _), (c2, _)| c1.partial_cmp(c2).unwrap());
-    cracks
+    Ok(cracks)
 }
 
-fn print_cracks(cracks: &Vec<(f64, usize)>, cipher: &str) {
-    for (chi, key) in &cracks[..5] {
-        println!("{} {:>2} {}", 
-            decrypt(cipher, *key).unwrap(),
-            key, 
-            chi);
+impl Cipher for Caesar {
+    type Key = usize;
+
+    fn name(&self) -> &'static str {
+        "caesar"
+    }
+
+    fn encrypt(&self, plaintext: &str, key: &usize) -> Result<String, String> {
+        encrypt(plaintext, *key)
+    }
+
+    fn decrypt(&self, ciphertext: &str, key: &usize) -> Result<String, String> {
+        decrypt(ciphertext, *key)
     }
-}
 
-pub fn main() {
-    let text = "helloworld";
-    let ciphertext = encrypt(text, 23).unwrap();
-    let plaintext = decrypt(&ciphertext, 23).unwrap();
-    println!("Ciphertext: {}", ciphertext);
-    println!("Plaintext: {}", plaintext);
+    fn parse_key(&self, key: &str) -> Result<usize, String> {
+        key.trim().parse::<usize>()
+            .map_err(|_| format!("Key '{key}' is not a number"))
+    }
 
-    let cracks = crack(&ciphertext, &ENGLISH_FREQ);
-    print_cracks(&cracks, &ciphertext);
+    fn format_key(&self, key: &usize) -> String {
+        key.to_string()
+    }
+
+    fn generate_key<R: Rng + ?Sized>(&self, rng: &mut R) -> usize {
+        rng.gen_range(1..=26)
+    }
 }
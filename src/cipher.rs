@@ -0,0 +1,67 @@
+use rand::Rng;
+
+use crate::english::ALPHABET;
+use crate::normalise::Layout;
+
+/// Operations shared by every cipher, so tooling can be written once over
+/// any of them.
+pub trait Cipher {
+    type Key;
+
+    fn name(&self) -> &'static str;
+
+    fn encrypt(&self, plaintext: &str, key: &Self::Key) -> Result<String, String>;
+
+    fn decrypt(&self, ciphertext: &str, key: &Self::Key) -> Result<String, String>;
+
+    /// Parses a key written by [`Cipher::format_key`].
+    fn parse_key(&self, key: &str) -> Result<Self::Key, String>;
+
+    fn format_key(&self, key: &Self::Key) -> String;
+
+    fn generate_key<R: Rng + ?Sized>(&self, rng: &mut R) -> Self::Key;
+
+    /// Encrypts the letters of any text, keeping its case, spacing and
+    /// punctuation in place.
+    fn encrypt_text(&self, plaintext: &str, key: &Self::Key) -> Result<String, String> {
+        let layout = Layout::new(plaintext);
+        Ok(layout.render(&self.encrypt(layout.letters(), key)?))
+    }
+
+    /// Decrypts the letters of any text, see [`Cipher::encrypt_text`].
+    fn decrypt_text(&self, ciphertext: &str, key: &Self::Key) -> Result<String, String> {
+        let layout = Layout::new(ciphertext);
+        Ok(layout.render(&self.decrypt(layout.letters(), key)?))
+    }
+}
+
+/// Parses 26 distinct letters, as used by substitution and Enigma keys.
+pub fn parse_permutation(key: &str) -> Result<[char; 26], String> {
+    let chars: Vec<char> = key.chars().collect();
+    if chars.len() != 26 {
+        return Err(format!("Key '{key}' must have 26 letters"));
+    }
+    let mut permutation = ['a'; 26];
+    let mut seen = [false; 26];
+    for (idx, key_char) in chars.into_iter().enumerate() {
+        if !key_char.is_ascii_lowercase() {
+            return Err(format!("Char '{key_char}' is out of range a..=z"));
+        }
+        let bin = key_char as usize - 97;
+        if seen[bin] {
+            return Err(format!("Char '{key_char}' appears twice in key"));
+        }
+        seen[bin] = true;
+        permutation[idx] = key_char;
+    }
+    Ok(permutation)
+}
+
+/// Returns the permutation mapping `key[i]` back to `ALPHABET[i]`.
+pub fn reverse_permutation(key: &[char; 26]) -> [char; 26] {
+    let mut rev = ['a'; 26];
+    for (idx, value) in key.iter().enumerate() {
+        rev[*value as usize - 97] = ALPHABET[idx];
+    }
+    rev
+}
@@ -1,213 +1,492 @@
-use rand::seq::SliceRandom;
-use rand::thread_rng;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
 
-use crate::english::ALPHABET;
-use crate::fitness::{
-    FitnessMatrix,
-    compute_fitness,
-    generate_fitness_matrix_from_file};
+use crate::enigma_machine::{KeySheet, Machine, Plugboard, Reflector, Rotor};
+use crate::fitness::{FitnessMatrix, compute_fitness};
+use crate::stats::index_of_coincidence;
 
-struct EnigmaKey([char; 26], [char; 26], [char; 26]);
+/// Fewest letters the statistics of the attack mean anything for.
+pub const MIN_LENGTH: usize = 50;
 
-fn generate_key() -> [char; 26] {
-    let mut key = ALPHABET.clone();
-    key.shuffle(&mut thread_rng());
-    key
+/// Settings of the ciphertext-only attack, [`crack`].
+#[derive(Clone, Debug)]
+pub struct CrackOptions {
+    /// Rotors tried in each of the three places.
+    pub rotors: Vec<Rotor>,
+    pub reflectors: Vec<Reflector>,
+    /// Keys with the best IC kept for the plugboard and ring searches.
+    pub candidates: usize,
+    /// Most plugs the plugboard hill climb puts in.
+    pub max_plugs: usize,
+    /// Number of worker threads trying wheel orders.
+    pub threads: usize,
+    /// Print progress to stderr.
+    pub report: bool,
 }
 
-fn generate_enigmakey() -> EnigmaKey {
-    EnigmaKey(generate_key(),
-        generate_key(),
-        generate_key())
+impl Default for CrackOptions {
+    /// The five rotors and two reflectors of the Enigma I.
+    fn default() -> Self {
+        CrackOptions {
+            rotors: Rotor::ALL[..5].to_vec(),
+            reflectors: Reflector::ALL.to_vec(),
+            candidates: 50,
+            max_plugs: 10,
+            threads: 1,
+            report: false,
+        }
+    }
+}
+
+/// Fitness of the decryption of `ciphertext` (letters as 0..26) with `key`.
+fn score(key: &KeySheet, ciphertext: &[u8], fitness: &dyn Fn(&[u8]) -> f64) -> f64 {
+    let mut machine = Machine::new(key);
+    let plaintext: Vec<u8> = ciphertext.iter().map(|x| machine.press(*x)).collect();
+    fitness(&plaintext)
 }
 
-fn rotate_key(key: &EnigmaKey) -> [[[char; 26]; 26]; 3] {
-    let mut rotated_key = [[['a'; 26]; 26]; 3];
-    let vec_key = [key.0, key.1, key.2];
-    for rotor_num in 0..3 {
-        for rotate_num in 0..26 {
-            let mut rotor = vec_key[rotor_num].clone();
-            rotor.rotate_left(rotate_num);
-            rotated_key[rotor_num][rotate_num] = rotor;
+/// One wheel order with rings at `A` and no plugs, tabulated so that a letter
+/// costs three lookups: the right wheel both ways at each of its positions,
+/// and the path from the middle wheel to the reflector and back at each
+/// position of the left and middle wheels.
+pub(crate) struct WheelTables {
+    right: [[u8; 26]; 26],
+    right_back: [[u8; 26]; 26],
+    inner: Vec<[u8; 26]>,
+    middle_notches: [bool; 26],
+    right_notches: [bool; 26],
+}
+
+impl WheelTables {
+    pub(crate) fn new(key: &KeySheet) -> WheelTables {
+        let mut machine = Machine::new(key);
+        let mut tables = WheelTables {
+            right: [[0; 26]; 26],
+            right_back: [[0; 26]; 26],
+            inner: vec![[0; 26]; 676],
+            middle_notches: [false; 26],
+            right_notches: [false; 26],
+        };
+        for position in 0..26u8 {
+            machine.set_positions(&[position; 3]);
+            let [_, middle, right] = &machine.wheels[..] else { unreachable!() };
+            tables.middle_notches[position as usize] = middle.at_notch();
+            tables.right_notches[position as usize] = right.at_notch();
+            for x in 0..26u8 {
+                tables.right[position as usize][x as usize] = right.pass(x, true);
+                tables.right_back[position as usize][x as usize] = right.pass(x, false);
+            }
+            for middle_position in 0..26u8 {
+                machine.set_positions(&[position, middle_position, 0]);
+                let [left, middle, _] = &machine.wheels[..] else { unreachable!() };
+                for x in 0..26u8 {
+                    let y = machine.reflector[left.pass(middle.pass(x, true), true) as usize];
+                    tables.inner[position as usize * 26 + middle_position as usize][x as usize] =
+                        middle.pass(left.pass(y, false), false);
+                }
+            }
+        }
+        tables
+    }
+
+    /// Path of `x` through the wheels and back, with the left and middle
+    /// wheels at `state`, `left * 26 + middle`, and the right one at `right`.
+    pub(crate) fn map(&self, state: usize, right: usize, x: u8) -> u8 {
+        let y = self.inner[state][self.right[right][x as usize] as usize];
+        self.right_back[right][y as usize]
+    }
+
+    /// Whether the key press made with the right wheel at `right` turns the
+    /// middle wheel on.
+    pub(crate) fn turns_middle(&self, right: usize) -> bool {
+        self.right_notches[right]
+    }
+
+    /// Left and middle positions, as `left * 26 + middle`, after the key
+    /// press made with the right wheel at `right`; see [`Machine::step`].
+    fn step(&self, state: usize, right: usize) -> usize {
+        let (left, middle) = (state / 26, state % 26);
+        if self.middle_notches[middle] {
+            (left + 1) % 26 * 26 + (middle + 1) % 26
+        } else if self.right_notches[right] {
+            left * 26 + (middle + 1) % 26
+        } else {
+            state
         }
     }
-    rotated_key
 }
 
-fn encrypt(plaintext: &str, key: &EnigmaKey) -> Result<String, String> {
-    let rotated_key = rotate_key(key);
-    let mut ciphertext = String::new();
-    for (idx, plaintext_char) in plaintext.chars().enumerate() {
-        if plaintext_char < 'a' || plaintext_char > 'z' {
-            return Err(format!("Invalid character {plaintext_char} in plaintext"));
+/// IC of the decryptions from one start of the left and middle wheels and
+/// one offset of the right wheel's wiring, for each ring of the right wheel
+/// in turn. Moving the ring on by one moves every turnover one letter
+/// earlier and leaves the rest alone, so only the letters at turnovers are
+/// deciphered again.
+struct TurnoverScan<'a> {
+    tables: &'a WheelTables,
+    /// Each letter after the right wheel, which the ring does not change.
+    entry: &'a [u8],
+    offset: usize,
+    start: usize,
+    /// Left and middle positions at each letter, from `base` on.
+    states: Vec<usize>,
+    base: usize,
+    /// Indices of `states` at which the state changed.
+    turnovers: VecDeque<usize>,
+    plaintext: Vec<u8>,
+    counts: [usize; 26],
+    /// Sum of `n * (n - 1)` over `counts`.
+    pairs: usize,
+}
+
+impl<'a> TurnoverScan<'a> {
+    fn new(tables: &'a WheelTables, entry: &'a [u8], offset: usize) -> TurnoverScan<'a> {
+        TurnoverScan {
+            tables,
+            entry,
+            offset,
+            start: 0,
+            states: Vec::with_capacity(entry.len() + 26),
+            base: 0,
+            turnovers: VecDeque::new(),
+            plaintext: vec![0; entry.len()],
+            counts: [0; 26],
+            pairs: 0,
         }
-        let rotor1 = &rotated_key[0][idx%26];
-        let rotor2 = &rotated_key[1][idx/26%26];
-        let rotor3 = &rotated_key[2][idx/(26*26)%26];
+    }
 
-        let plaintext_bin = plaintext_char as usize - 97;
-        let round1 = rotor1[plaintext_bin] as usize - 97;
-        let round2 = rotor2[round1] as usize - 97;
-        let round3 = rotor3[round2];
-        ciphertext.push(round3);
+    fn decipher(&self, idx: usize, state: usize) -> u8 {
+        let y = self.tables.inner[state][self.entry[idx] as usize];
+        self.tables.right_back[(self.offset + idx + 1) % 26][y as usize]
     }
-    Ok(ciphertext)
-}
 
-fn reverse_key(key: &[[[char; 26]; 26]; 3]) -> [[[char; 26]; 26]; 3] {
-    fn reverse_rotor(rotor: &[char; 26]) -> [char; 26] {
-        let mut rev_rotor = ['a'; 26];
-        for (idx, value) in rotor.iter().enumerate() {
-            let to = ALPHABET[idx];
-            let from = *value as usize - 97;
-            rev_rotor[from] = to;
+    /// Deciphers everything from `start` with the right ring at `ring`.
+    fn reset(&mut self, start: usize, ring: usize) {
+        self.start = start;
+        self.states.clear();
+        self.base = 0;
+        self.turnovers.clear();
+        self.counts = [0; 26];
+        let mut state = start;
+        for idx in 0..self.entry.len() {
+            state = self.tables.step(state, (self.offset + ring + idx) % 26);
+            if idx > 0 && state != self.states[idx - 1] {
+                self.turnovers.push_back(idx);
+            }
+            self.states.push(state);
+            let x = self.decipher(idx, state);
+            self.plaintext[idx] = x;
+            self.counts[x as usize] += 1;
         }
-        rev_rotor
+        self.pairs = self.counts.iter().map(|n| n * n.saturating_sub(1)).sum();
     }
-    let mut rev = key.clone();
-    for i in 0..3 {
-        for j in 0..26 {
-            rev[i][j] = reverse_rotor(&rev[i][j]);
+
+    /// Moves from the previous ring to `ring`, unless the first letter had a
+    /// turnover which would now come before the start.
+    fn next_ring(&mut self, ring: usize) -> bool {
+        if self.states[self.base] != self.start {
+            return false;
+        }
+        let len = self.entry.len();
+        let last = self.states[self.base + len - 1];
+        let state = self.tables.step(last, (self.offset + ring + len - 1) % 26);
+        if state != last {
+            self.turnovers.push_back(self.base + len);
+        }
+        self.states.push(state);
+        self.base += 1;
+        while self.turnovers.front().is_some_and(|turnover| *turnover < self.base) {
+            self.turnovers.pop_front();
         }
+        for turnover in self.turnovers.iter() {
+            let idx = turnover - self.base;
+            let x = self.decipher(idx, self.states[*turnover]) as usize;
+            let old = self.plaintext[idx] as usize;
+            self.pairs -= 2 * (self.counts[old] - 1);
+            self.counts[old] -= 1;
+            self.pairs += 2 * self.counts[x];
+            self.counts[x] += 1;
+            self.plaintext[idx] = x as u8;
+        }
+        true
+    }
+
+    fn ic(&self) -> f64 {
+        let len = self.entry.len();
+        self.pairs as f64 / (len * (len - 1)) as f64
     }
-    rev
 }
 
-fn decrypt(ciphertext: &str, key: &EnigmaKey) -> Result<String, String> {
-    let rotated_key = rotate_key(&key);
-    let rotated_key = reverse_key(&rotated_key);
-    let mut plaintext = String::new();
-    for (idx, ciphertext_char) in ciphertext.chars().enumerate() {
-        let rotor1 = &rotated_key[0][idx%26];
-        let rotor2 = &rotated_key[1][idx/26%26];
-        let rotor3 = &rotated_key[2][idx/(26*26)%26];
-
-        let ciphertext_bin = ciphertext_char as usize - 97;
-        let round1 = rotor3[ciphertext_bin] as usize - 97;
-        let round2 = rotor2[round1] as usize - 97;
-        let round3 = rotor1[round2];
-        plaintext.push(round3);
-    }
-    Ok(plaintext)
+/// Every wheel order of three different rotors of `rotors`, with each
+/// reflector.
+pub(crate) fn wheel_orders(options: &CrackOptions) -> Vec<(Reflector, [Rotor; 3])> {
+    let mut orders = Vec::new();
+    for reflector in &options.reflectors {
+        for left in &options.rotors {
+            for middle in options.rotors.iter().filter(|x| *x != left) {
+                for right in options.rotors.iter().filter(|x| *x != left && *x != middle) {
+                    orders.push((*reflector, [*left, *middle, *right]));
+                }
+            }
+        }
+    }
+    orders
 }
 
-fn fast_decrypt(ciphertext: &str, 
-    rotated_key: &[[[char; 26]; 26]; 3],
-    plaintext: &mut Vec<u8>) 
-{
-    for (idx, ciphertext_char) in ciphertext.chars().enumerate() {
-        let rotor1 = &rotated_key[0][idx%26];
-        let rotor2 = &rotated_key[1][idx/26%26];
-        let rotor3 = &rotated_key[2][idx/(26*26)%26];
+/// Tries every wheel order, start position and ring of the right wheel with
+/// no plugs, keeping the `options.candidates` keys whose decryption has the
+/// highest IC. The plugboard leaves some letters in place, so the right
+/// settings still stand out from random text. The right ring matters as it
+/// decides where the middle wheel turns over; the others seldom do.
+fn search_positions(ciphertext: &[u8], options: &CrackOptions) -> Vec<(f64, KeySheet)> {
+    let orders = wheel_orders(options);
+    let next_order = AtomicUsize::new(0);
+    let done = AtomicUsize::new(0);
+    let found: Mutex<Vec<(f64, usize, KeySheet)>> = Mutex::new(Vec::new());
+    let keep = options.candidates.max(1);
 
-        let ciphertext_bin = ciphertext_char as usize - 97;
-        let round1 = rotor3[ciphertext_bin] as usize - 97;
-        let round2 = rotor2[round1] as usize - 97;
-        let round3 = rotor1[round2] as u8 - 97;
+    let worker = || {
+        let mut best: Vec<(f64, usize, KeySheet)> = Vec::new();
+        loop {
+            let idx = next_order.fetch_add(1, Ordering::Relaxed);
+            let Some((reflector, rotors)) = orders.get(idx) else {
+                break;
+            };
+            let mut key = KeySheet {
+                reflector: *reflector,
+                rotors: *rotors,
+                rings: [0; 3],
+                positions: [0; 3],
+                plugboard: Plugboard::default(),
+            };
+            let tables = WheelTables::new(&key);
+            for offset in 0..26 {
+                let entry: Vec<u8> = ciphertext.iter().enumerate()
+                    .map(|(pos, x)| tables.right[(offset + pos + 1) % 26][*x as usize])
+                    .collect();
+                let mut scan = TurnoverScan::new(&tables, &entry, offset);
+                for start in 0..676 {
+                    for ring in 0..26 {
+                        if ring == 0 || !scan.next_ring(ring) {
+                            scan.reset(start, ring);
+                        }
+                        let ic = scan.ic();
+                        if best.len() < keep || ic > best[best.len() - 1].0 {
+                            key.rings = [0, 0, ring as u8];
+                            key.positions = [(start / 26) as u8, (start % 26) as u8,
+                                ((offset + ring) % 26) as u8];
+                            let found = ((idx * 26 + offset) * 676 + start) * 26 + ring;
+                            best.push((ic, found, key.clone()));
+                            best.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap().then(a.1.cmp(&b.1)));
+                            best.truncate(keep);
+                        }
+                    }
+                }
+            }
+            let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+            if options.report {
+                eprintln!("{done}/{} wheel orders tried", orders.len());
+            }
+        }
+        found.lock().unwrap().append(&mut best);
+    };
+    thread::scope(|scope| {
+        for _ in 1..options.threads {
+            scope.spawn(worker);
+        }
+        worker();
+    });
 
-        plaintext[idx] = round3;
-    }
+    // Sorting by where each key was found as well keeps the result the same
+    // whatever the number of threads.
+    let mut found = found.into_inner().unwrap();
+    found.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap().then(a.1.cmp(&b.1)));
+    found.truncate(keep);
+    found.into_iter().map(|(ic, _, key)| (ic, key)).collect()
 }
 
-fn fast_swap_key(rotated_key: &mut [[[char; 26]; 26]; 3], 
-    rotor_num: usize, char_from: char, char_to: char) 
+/// Tries the rings of the middle and right wheels, turning their positions
+/// along so the wirings line up as before: only the turnovers move. The left
+/// ring never matters, its position makes up for it. The left and middle
+/// wheels are also tried one position back, for when the search found them
+/// after they turned over partway through the message.
+fn refine_rings(key: &KeySheet, ciphertext: &[u8], fitness: &dyn Fn(&[u8]) -> f64)
+    -> (f64, KeySheet)
 {
-    let from = char_from as usize - 97;
-    let to = char_to as usize - 97;
-    rotated_key[rotor_num].iter_mut()
-        .for_each(move |key| key.swap(from, to));
+    let mut best = (score(key, ciphertext, fitness), key.clone());
+    for back in 0..4 {
+        for middle in 0..26 {
+            for right in 0..26 {
+                let mut candidate = key.clone();
+                candidate.rings[1] = (key.rings[1] + middle) % 26;
+                candidate.rings[2] = (key.rings[2] + right) % 26;
+                candidate.positions[0] = (key.positions[0] + 26 - back / 2) % 26;
+                candidate.positions[1] = (key.positions[1] + 26 + middle - back % 2) % 26;
+                candidate.positions[2] = (key.positions[2] + right) % 26;
+                let candidate_fitness = score(&candidate, ciphertext, fitness);
+                if candidate_fitness > best.0 {
+                    best = (candidate_fitness, candidate);
+                }
+            }
+        }
+    }
+    best
 }
 
-
-fn hill_climb(ciphertext: &str, init_key: &EnigmaKey, 
-                matrix: &FitnessMatrix) -> (f64, EnigmaKey) 
+/// Hill climbs the plugboard of `key`: connecting or disconnecting a pair of
+/// letters, unplugging whatever either was plugged to, until no change
+/// improves `fitness`.
+fn climb_plugboard(key: &KeySheet, ciphertext: &[u8], max_plugs: usize,
+    fitness: &dyn Fn(&[u8]) -> f64) -> (f64, KeySheet)
 {
-    let mut plaintext: Vec<u8> = decrypt(ciphertext, init_key)
-        .unwrap()
-        .chars()
-        .map(|x| x as u8 - 97)
-        .collect();
-    let mut vec_key = [init_key.0, init_key.1, init_key.2];
-    let mut rotated_key = reverse_key(&rotate_key(&init_key));
-
-    let mut current = compute_fitness(&plaintext, matrix);
-    loop {
-        let mut better_key = false;
-        for rotor_num in 0..3 {
-            for from in 0..26 {
-                for to in from+1..26 {
-                    fast_swap_key(&mut rotated_key, rotor_num, 
-                        vec_key[rotor_num][from], 
-                        vec_key[rotor_num][to]);
-                    fast_decrypt(ciphertext, &rotated_key, &mut plaintext);
-
-                    let proposal = compute_fitness(&plaintext, matrix);
-                    if proposal > current {
-                        vec_key[rotor_num].swap(to, from);
-                        current = proposal;
-                        better_key = true;
-                    } else {
-                        fast_swap_key(&mut rotated_key, rotor_num, 
-                            vec_key[rotor_num][from], 
-                            vec_key[rotor_num][to]);
+    let mut key = key.clone();
+    let mut best = score(&key, ciphertext, fitness);
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for a in 0..26 {
+            for b in a + 1..26 {
+                let plugs = &key.plugboard.0;
+                let mut candidate = key.clone();
+                if plugs.contains(&(a, b)) || plugs.contains(&(b, a)) {
+                    candidate.plugboard.0.retain(|plug| *plug != (a, b) && *plug != (b, a));
+                } else {
+                    candidate.plugboard.0
+                        .retain(|(x, y)| ![a, b].contains(x) && ![a, b].contains(y));
+                    if candidate.plugboard.0.len() >= max_plugs {
+                        continue;
                     }
+                    candidate.plugboard.0.push((a, b));
+                }
+                let candidate_fitness = score(&candidate, ciphertext, fitness);
+                if candidate_fitness > best {
+                    best = candidate_fitness;
+                    key = candidate;
+                    improved = true;
                 }
             }
         }
-        if !better_key { break; }
     }
-    let key = EnigmaKey(vec_key[0], vec_key[1], vec_key[2]);
-    (current, key)
+    key.plugboard.0.sort();
+    (best, key)
 }
 
-fn crack(ciphertext: &str) -> Result<(f64, EnigmaKey), &str> {
-    if ciphertext.len() < 10 || ciphertext.len() > 1000000 {
-        return Err("Length of cipher must in range 10..=1000000");
-    }
-    let matrix = generate_fitness_matrix_from_file();
-    let mut local_maximum = 0.0;
-    let mut local_maximum_hit = 0;
-    let mut best_key = generate_enigmakey();
-    for i in 1..1000000 {
-        if i % 1000 == 0 {
-            println!("{i} processed {:.4}", local_maximum);
-        }
+/// Completes the plugboard of `key`, whose wheels and rings are known, from
+/// the plugs already in it by hill climbs on `bigrams` and then `trigrams`.
+/// Returns the fitness of the decryption by `trigrams`, and the key.
+pub(crate) fn complete_plugboard(key: &KeySheet, ciphertext: &[u8], bigrams: &FitnessMatrix,
+    trigrams: &FitnessMatrix, max_plugs: usize) -> (f64, KeySheet)
+{
+    let bigram_fitness = |text: &[u8]| compute_fitness(text, bigrams);
+    let trigram_fitness = |text: &[u8]| compute_fitness(text, trigrams);
+    let (_, key) = climb_plugboard(key, ciphertext, max_plugs, &bigram_fitness);
+    climb_plugboard(&key, ciphertext, max_plugs, &trigram_fitness)
+}
+
+/// Completes the wheel order and positions of `key`, with any plugs already
+/// known: the plugboard by hill climbs on IC and `bigrams`, the rings on
+/// `trigrams`, and a last plugboard climb on `trigrams`. Returns the fitness
+/// of the decryption by `trigrams`, and the key.
+fn refine(key: &KeySheet, ciphertext: &[u8], bigrams: &FitnessMatrix,
+    trigrams: &FitnessMatrix, max_plugs: usize) -> (f64, KeySheet)
+{
+    let bigram_fitness = |text: &[u8]| compute_fitness(text, bigrams);
+    let trigram_fitness = |text: &[u8]| compute_fitness(text, trigrams);
+    let (_, key) = climb_plugboard(key, ciphertext, max_plugs, &index_of_coincidence);
+    let (_, key) = climb_plugboard(&key, ciphertext, max_plugs, &bigram_fitness);
+    // One letter in 26 is wrong with the turnover of the middle wheel one
+    // letter out, too few for the IC to tell but not for trigrams.
+    let (_, key) = refine_rings(&key, ciphertext, &trigram_fitness);
+    climb_plugboard(&key, ciphertext, max_plugs, &trigram_fitness)
+}
 
-        let init_key = generate_enigmakey();
-        let (fitness, rev_key) = hill_climb(ciphertext, &init_key, &matrix);
+/// Ciphertext-only attack on the Enigma I and M3, after Gillogly and
+/// Weierud & Sullivan: every wheel order, start position and right ring
+/// scored by IC, then the plugboard and rings of the best recovered by hill
+/// climbs on `bigrams` and `trigrams`. Returns the fitness of the best
+/// decryption by `trigrams`, and its key.
+///
+/// The plugboard hides the IC of the plaintext: with six plugs several
+/// hundred letters do, with the ten of the war years the right settings
+/// seldom stand out even in a long message.
+pub fn crack(ciphertext: &str, bigrams: &FitnessMatrix, trigrams: &FitnessMatrix,
+    options: &CrackOptions) -> Result<(f64, KeySheet), &'static str>
+{
+    if ciphertext.len() < MIN_LENGTH {
+        return Err("Cipher must have at least 50 letters");
+    }
+    if !ciphertext.chars().all(|x| x.is_ascii_lowercase()) {
+        return Err("Cipher must only contain a..=z");
+    }
+    if options.rotors.len() < 3 || options.reflectors.is_empty() {
+        return Err("Attack needs at least three rotors and a reflector");
+    }
+    let ciphertext: Vec<u8> = ciphertext.bytes().map(|x| x - b'a').collect();
 
-        if fitness > local_maximum {
-            local_maximum = fitness;
-            best_key = rev_key;
-        } else if fitness == local_maximum {
-            local_maximum_hit += 1;
+    let candidates = search_positions(&ciphertext, options);
+    let mut best: Option<(f64, KeySheet)> = None;
+    for (idx, (ic, key)) in candidates.iter().enumerate() {
+        let (fitness, key) = refine(key, &ciphertext, bigrams, trigrams, options.max_plugs);
+        if options.report {
+            eprintln!("Candidate {}/{} (IC {ic:.4}): fitness {fitness:.4}, key {}",
+                idx + 1, candidates.len(), key.format());
         }
-        if local_maximum_hit == 100 {
-            break;
+        if best.as_ref().is_none_or(|(best, _)| fitness > *best) {
+            best = Some((fitness, key));
         }
     }
-    Ok((local_maximum, best_key))
+    best.ok_or("No candidate settings found")
 }
 
-pub fn main() {
-    let plaintext = "to be or not to be that is the question"
-        .replace(" ", "");
-    let key = generate_enigmakey();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    /*
-    let mut rotated_key = reverse_key(&rotate_key(&init_key));
+    const TEXT: &str = "itwasthebestoftimesitwastheworstoftimesitwastheageofwisdomitwastheageoff\
+    oolishnessitwastheepochofbeliefitwastheepochofincredulityitwastheseasono\
+    flightitwastheseasonofdarknessitwasthespringofhopeitwasthewinterofdespai\
+    rwehadeverythingbeforeuswehadnothingbeforeuswewereallgoingdirecttoheaven\
+    wewereallgoingdirecttheotherwayinshortthep";
 
-    init_key.0.swap(1, 5);
-    let swap_rotated_key = reverse_key(&rotate_key(&init_key));
-    
-    fast_swap_key(&mut rotated_key, 0, init_key.0[1], init_key.0[5]);
-
-    assert_eq!(swap_rotated_key, rotated_key);
-    */
-
-    let ciphertext = encrypt(&plaintext, &key).unwrap();
+    #[test]
+    fn refine_rings_finds_the_left_wheel_before_its_turnover() {
+        let key = KeySheet::parse("B:II-I-III:05-11-20:QKV:AV-BS-CG-DL").unwrap();
+        let plaintext: Vec<u8> = TEXT.bytes().map(|x| x - b'a').collect();
+        let mut machine = Machine::new(&key);
+        let ciphertext: Vec<u8> = plaintext.iter().map(|x| machine.press(*x)).collect();
+        // Where the IC search finds the wheels: after the left one turned over.
+        let found = KeySheet::parse("B:II-I-III:01-01-20:NBV:AV-BS-CG-DL").unwrap();
+        let matches = |text: &[u8]| {
+            text.iter().zip(&plaintext).filter(|(x, y)| x == y).count() as f64
+        };
+        let (fitness, _) = refine_rings(&found, &ciphertext, &matches);
+        assert_eq!(fitness, plaintext.len() as f64);
+    }
 
-    let (fitness, guess_key) = crack(&ciphertext).unwrap();
-    println!("Fitness: {}", fitness);
-    println!("{}", decrypt(&ciphertext, &guess_key).unwrap());
+    #[test]
+    fn turnover_scan_next_ring_matches_reset() {
+        let key = KeySheet::parse("B:II-I-III:01-01-01:AAA:").unwrap();
+        let tables = WheelTables::new(&key);
+        let ciphertext: Vec<u8> = TEXT.bytes().map(|x| x - b'a').collect();
+        // Starts with the middle wheel, I, at and next to its notch at Q.
+        for start in [0, 119, 120, 121, 675] {
+            for offset in [0, 7, 25] {
+                let entry: Vec<u8> = ciphertext.iter().enumerate()
+                    .map(|(pos, x)| tables.right[(offset + pos + 1) % 26][*x as usize])
+                    .collect();
+                let mut scan = TurnoverScan::new(&tables, &entry, offset);
+                let mut fresh = TurnoverScan::new(&tables, &entry, offset);
+                scan.reset(start, 0);
+                for ring in 1..26 {
+                    if !scan.next_ring(ring) {
+                        scan.reset(start, ring);
+                    }
+                    fresh.reset(start, ring);
+                    assert_eq!(scan.ic(), fresh.ic(), "start {start} offset {offset} ring {ring}");
+                    assert_eq!(scan.plaintext, fresh.plaintext);
+                }
+            }
+        }
+    }
 }
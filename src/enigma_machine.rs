@@ -0,0 +1,695 @@
+use rand::seq::SliceRandom;
+use rand::Rng;
+
+use crate::cipher::Cipher;
+use crate::english::ALPHABET;
+
+/// The wheels of the Enigma I and M3: wirings and the positions shown in
+/// the window when the next wheel is carried.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Rotor {
+    I,
+    II,
+    III,
+    IV,
+    V,
+    VI,
+    VII,
+    VIII,
+}
+
+impl Rotor {
+    pub const ALL: [Rotor; 8] = [
+        Rotor::I, Rotor::II, Rotor::III, Rotor::IV,
+        Rotor::V, Rotor::VI, Rotor::VII, Rotor::VIII,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Rotor::I => "I",
+            Rotor::II => "II",
+            Rotor::III => "III",
+            Rotor::IV => "IV",
+            Rotor::V => "V",
+            Rotor::VI => "VI",
+            Rotor::VII => "VII",
+            Rotor::VIII => "VIII",
+        }
+    }
+
+    pub fn wiring(&self) -> &'static str {
+        match self {
+            Rotor::I => "EKMFLGDQVZNTOWYHXUSPAIBRCJ",
+            Rotor::II => "AJDKSIRUXBLHWTMCQGZNPYFVOE",
+            Rotor::III => "BDFHJLCPRTXVZNYEIWGAKMUSQO",
+            Rotor::IV => "ESOVPZJAYQUIRHXLNFTGKDCMWB",
+            Rotor::V => "VZBRGITYUPSDNHLXAWMJQOFECK",
+            Rotor::VI => "JPGVOUMFYQBENHZRDKASXLICTW",
+            Rotor::VII => "NZJHGRCXMYSWBOUFAIVLPEKQDT",
+            Rotor::VIII => "FKQHTLXOCBJSPDZRAMEWNIUYGV",
+        }
+    }
+
+    /// Window letters at which the wheel carries the next one on.
+    pub fn notches(&self) -> &'static str {
+        match self {
+            Rotor::I => "Q",
+            Rotor::II => "E",
+            Rotor::III => "V",
+            Rotor::IV => "J",
+            Rotor::V => "Z",
+            Rotor::VI | Rotor::VII | Rotor::VIII => "ZM",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Rotor, String> {
+        Rotor::ALL.into_iter()
+            .find(|rotor| rotor.name().eq_ignore_ascii_case(name))
+            .ok_or(format!("Invalid rotor {name} (use I to VIII)"))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Reflector {
+    B,
+    C,
+}
+
+impl Reflector {
+    pub const ALL: [Reflector; 2] = [Reflector::B, Reflector::C];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Reflector::B => "B",
+            Reflector::C => "C",
+        }
+    }
+
+    pub fn wiring(&self) -> &'static str {
+        match self {
+            Reflector::B => "YRUHQSLDPXNGOKMIEBFZCWVJAT",
+            Reflector::C => "FVPJIAOYEDRZXWGCTKUQSBNMHL",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<Reflector, String> {
+        Reflector::ALL.into_iter()
+            .find(|reflector| reflector.name().eq_ignore_ascii_case(name))
+            .ok_or(format!("Invalid reflector {name} (use B or C)"))
+    }
+}
+
+/// The fourth wheel of the M4, left of the others. It never moves while
+/// typing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum GreekWheel {
+    Beta,
+    Gamma,
+}
+
+impl GreekWheel {
+    pub const ALL: [GreekWheel; 2] = [GreekWheel::Beta, GreekWheel::Gamma];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            GreekWheel::Beta => "Beta",
+            GreekWheel::Gamma => "Gamma",
+        }
+    }
+
+    pub fn wiring(&self) -> &'static str {
+        match self {
+            GreekWheel::Beta => "LEYJVCNIXWPBQMDRTAKZGFUHOS",
+            GreekWheel::Gamma => "FSOKANUERHMBTIYCWLQPZXVGJD",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<GreekWheel, String> {
+        GreekWheel::ALL.into_iter()
+            .find(|wheel| wheel.name().eq_ignore_ascii_case(name))
+            .ok_or(format!("Invalid Greek wheel {name} (use Beta or Gamma)"))
+    }
+}
+
+/// Reflectors of the M4, thin to make room for the Greek wheel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ThinReflector {
+    B,
+    C,
+}
+
+impl ThinReflector {
+    pub const ALL: [ThinReflector; 2] = [ThinReflector::B, ThinReflector::C];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ThinReflector::B => "B",
+            ThinReflector::C => "C",
+        }
+    }
+
+    pub fn wiring(&self) -> &'static str {
+        match self {
+            ThinReflector::B => "ENKQAUYWJICOPBLMDXZVFTHRGS",
+            ThinReflector::C => "RDOBJNTKVEHMLFCWZAXGYIPSUQ",
+        }
+    }
+
+    pub fn parse(name: &str) -> Result<ThinReflector, String> {
+        ThinReflector::ALL.into_iter()
+            .find(|reflector| reflector.name().eq_ignore_ascii_case(name))
+            .ok_or(format!("Invalid thin reflector {name} (use B or C)"))
+    }
+}
+
+/// Letters swapped by the plugboard, as pairs of 0..26.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Plugboard(pub Vec<(u8, u8)>);
+
+impl Plugboard {
+    /// The plugboard as a permutation of 0..26.
+    pub fn wiring(&self) -> [u8; 26] {
+        let mut wiring: [u8; 26] = std::array::from_fn(|x| x as u8);
+        for (a, b) in &self.0 {
+            wiring[*a as usize] = *b;
+            wiring[*b as usize] = *a;
+        }
+        wiring
+    }
+
+    /// Parses pairs such as `AV BS CG`, or `AV-BS-CG`.
+    pub fn parse(text: &str) -> Result<Plugboard, String> {
+        let mut pairs = Vec::new();
+        let mut used = [false; 26];
+        for pair in text.split([' ', '-']).filter(|x| !x.is_empty()) {
+            let letters: Vec<u8> = pair.bytes().map(|x| x.to_ascii_lowercase()).collect();
+            if letters.len() != 2 || !letters.iter().all(u8::is_ascii_lowercase) {
+                return Err(format!("Invalid plug {pair}, use two letters"));
+            }
+            let (a, b) = (letters[0] - b'a', letters[1] - b'a');
+            if a == b || used[a as usize] || used[b as usize] {
+                return Err(format!("Letters of plug {pair} are used twice"));
+            }
+            used[a as usize] = true;
+            used[b as usize] = true;
+            pairs.push((a, b));
+        }
+        Ok(Plugboard(pairs))
+    }
+
+    pub fn format(&self) -> String {
+        self.0.iter()
+            .map(|(a, b)| format!("{}{}", (a + b'A') as char, (b + b'A') as char))
+            .collect::<Vec<String>>()
+            .join("-")
+    }
+}
+
+/// Daily key of an Enigma I or M3: reflector, wheel order (left to right),
+/// ring settings, start positions and plugboard. Rings and positions are
+/// 0..26, `A` or `01` being 0.
+///
+/// Written as `B:II-IV-V:02-21-12:BLA:AV-BS-CG`, the plugboard part may be
+/// left empty.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeySheet {
+    pub reflector: Reflector,
+    pub rotors: [Rotor; 3],
+    pub rings: [u8; 3],
+    pub positions: [u8; 3],
+    pub plugboard: Plugboard,
+}
+
+/// Parses ring settings written as numbers `01-21-12` or letters `AUL`.
+pub(crate) fn parse_settings<const N: usize>(text: &str) -> Result<[u8; N], String> {
+    let settings: Vec<u8> = if text.chars().all(|x| x.is_ascii_alphabetic()) {
+        text.bytes().map(|x| x.to_ascii_lowercase() - b'a').collect()
+    } else {
+        text.split('-')
+            .map(|x| match x.parse::<u8>() {
+                Ok(number @ 1..=26) => Ok(number - 1),
+                _ => Err(format!("Invalid ring setting {x}, use 01 to 26")),
+            })
+            .collect::<Result<Vec<u8>, String>>()?
+    };
+    settings.try_into()
+        .map_err(|_| format!("'{text}' must give {N} settings"))
+}
+
+/// Parses window letters such as `BLA`.
+pub(crate) fn parse_positions<const N: usize>(text: &str) -> Result<[u8; N], String> {
+    if !text.chars().all(|x| x.is_ascii_alphabetic()) {
+        return Err(format!("Invalid positions {text}, use letters"));
+    }
+    text.bytes()
+        .map(|x| x.to_ascii_lowercase() - b'a')
+        .collect::<Vec<u8>>()
+        .try_into()
+        .map_err(|_| format!("Positions '{text}' must have {N} letters"))
+}
+
+pub(crate) fn format_settings(settings: &[u8]) -> String {
+    settings.iter()
+        .map(|x| format!("{:02}", x + 1))
+        .collect::<Vec<String>>()
+        .join("-")
+}
+
+pub(crate) fn format_positions(positions: &[u8]) -> String {
+    positions.iter().map(|x| (x + b'A') as char).collect()
+}
+
+/// Splits a key sheet into its fields, the plugboard one being optional.
+fn key_fields<'a>(text: &'a str, example: &str) -> Result<Vec<&'a str>, String> {
+    let fields: Vec<&str> = text.trim().split(':').collect();
+    if !(4..=5).contains(&fields.len()) {
+        return Err(format!("Key '{text}' must look like {example}"));
+    }
+    Ok(fields)
+}
+
+/// Parses a wheel order of three different rotors, such as `II-IV-V`.
+fn parse_rotors(text: &str) -> Result<[Rotor; 3], String> {
+    let rotors: Vec<Rotor> = text.split('-')
+        .map(Rotor::parse)
+        .collect::<Result<Vec<Rotor>, String>>()?;
+    let rotors: [Rotor; 3] = rotors.try_into()
+        .map_err(|_| format!("Wheel order {text} must have 3 rotors"))?;
+    if rotors[0] == rotors[1] || rotors[0] == rotors[2] || rotors[1] == rotors[2] {
+        return Err(format!("Wheel order {text} uses a rotor twice"));
+    }
+    Ok(rotors)
+}
+
+fn random_rotors<R: Rng + ?Sized>(rng: &mut R) -> [Rotor; 3] {
+    let mut rotors = Rotor::ALL;
+    rotors.shuffle(rng);
+    [rotors[0], rotors[1], rotors[2]]
+}
+
+/// Ten random plugs, as was the rule from 1939.
+fn random_plugboard<R: Rng + ?Sized>(rng: &mut R) -> Plugboard {
+    let mut letters: Vec<u8> = (0..26).collect();
+    letters.shuffle(rng);
+    Plugboard(letters.chunks(2).take(10).map(|x| (x[0], x[1])).collect())
+}
+
+impl KeySheet {
+    pub fn parse(text: &str) -> Result<KeySheet, String> {
+        let fields = key_fields(text, "B:II-IV-V:02-21-12:BLA:AV-BS")?;
+        Ok(KeySheet {
+            reflector: Reflector::parse(fields[0])?,
+            rotors: parse_rotors(fields[1])?,
+            rings: parse_settings(fields[2])?,
+            positions: parse_positions(fields[3])?,
+            plugboard: Plugboard::parse(fields.get(4).unwrap_or(&""))?,
+        })
+    }
+
+    pub fn format(&self) -> String {
+        let rotors: Vec<&str> = self.rotors.iter().map(Rotor::name).collect();
+        format!("{}:{}:{}:{}:{}", self.reflector.name(), rotors.join("-"),
+            format_settings(&self.rings), format_positions(&self.positions),
+            self.plugboard.format())
+    }
+
+    /// A random key: wheel order, rings, positions and ten plugs.
+    pub fn generate<R: Rng + ?Sized>(rng: &mut R) -> KeySheet {
+        KeySheet {
+            reflector: *Reflector::ALL.choose(rng).unwrap(),
+            rotors: random_rotors(rng),
+            rings: std::array::from_fn(|_| rng.gen_range(0..26)),
+            positions: std::array::from_fn(|_| rng.gen_range(0..26)),
+            plugboard: random_plugboard(rng),
+        }
+    }
+}
+
+/// Daily key of the four-wheel naval M4: thin reflector, Greek wheel, then
+/// the three stepping rotors; rings and positions cover all four wheels.
+///
+/// Written as `B:Beta-II-IV-I:01-01-01-22:VJNA:AT-BL-DF`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct M4KeySheet {
+    pub reflector: ThinReflector,
+    pub greek: GreekWheel,
+    pub rotors: [Rotor; 3],
+    pub rings: [u8; 4],
+    pub positions: [u8; 4],
+    pub plugboard: Plugboard,
+}
+
+impl M4KeySheet {
+    pub fn parse(text: &str) -> Result<M4KeySheet, String> {
+        let fields = key_fields(text, "B:Beta-II-IV-I:01-01-01-22:VJNA:AT-BL")?;
+        let (greek, rotors) = fields[1].split_once('-')
+            .ok_or(format!("Wheel order {} must start with Beta or Gamma", fields[1]))?;
+        Ok(M4KeySheet {
+            reflector: ThinReflector::parse(fields[0])?,
+            greek: GreekWheel::parse(greek)?,
+            rotors: parse_rotors(rotors)?,
+            rings: parse_settings(fields[2])?,
+            positions: parse_positions(fields[3])?,
+            plugboard: Plugboard::parse(fields.get(4).unwrap_or(&""))?,
+        })
+    }
+
+    pub fn format(&self) -> String {
+        let rotors: Vec<&str> = self.rotors.iter().map(Rotor::name).collect();
+        format!("{}:{}-{}:{}:{}:{}", self.reflector.name(), self.greek.name(),
+            rotors.join("-"), format_settings(&self.rings),
+            format_positions(&self.positions), self.plugboard.format())
+    }
+
+    /// A random key: Greek wheel, wheel order, rings, positions and ten plugs.
+    pub fn generate<R: Rng + ?Sized>(rng: &mut R) -> M4KeySheet {
+        M4KeySheet {
+            reflector: *ThinReflector::ALL.choose(rng).unwrap(),
+            greek: *GreekWheel::ALL.choose(rng).unwrap(),
+            rotors: random_rotors(rng),
+            rings: std::array::from_fn(|_| rng.gen_range(0..26)),
+            positions: std::array::from_fn(|_| rng.gen_range(0..26)),
+            plugboard: random_plugboard(rng),
+        }
+    }
+}
+
+fn letters(wiring: &str) -> [u8; 26] {
+    let mut letters = [0; 26];
+    for (idx, x) in wiring.bytes().enumerate() {
+        letters[idx] = x.to_ascii_lowercase() - b'a';
+    }
+    letters
+}
+
+fn inverse(wiring: &[u8; 26]) -> [u8; 26] {
+    let mut inverse = [0; 26];
+    for (idx, x) in wiring.iter().enumerate() {
+        inverse[*x as usize] = idx as u8;
+    }
+    inverse
+}
+
+/// One wheel as mounted: its wirings both ways, notches, ring and window
+/// position.
+#[derive(Clone, Debug)]
+pub(crate) struct Wheel {
+    forward: [u8; 26],
+    backward: [u8; 26],
+    notches: [bool; 26],
+    ring: u8,
+    pub(crate) position: u8,
+}
+
+impl Wheel {
+    pub(crate) fn new(wiring: &str, notches: &str, ring: u8, position: u8) -> Wheel {
+        let forward = letters(wiring);
+        let mut notch = [false; 26];
+        for x in notches.bytes() {
+            notch[(x.to_ascii_lowercase() - b'a') as usize] = true;
+        }
+        Wheel { backward: inverse(&forward), forward, notches: notch, ring, position }
+    }
+
+    pub(crate) fn at_notch(&self) -> bool {
+        self.notches[self.position as usize]
+    }
+
+    fn step(&mut self) {
+        self.position = (self.position + 1) % 26;
+    }
+
+    /// Passes `x` through the wiring, right to left when `forward`.
+    pub(crate) fn pass(&self, x: u8, forward: bool) -> u8 {
+        let shift = (self.position + 26 - self.ring) % 26;
+        let contact = ((x + shift) % 26) as usize;
+        let wired = if forward { self.forward[contact] } else { self.backward[contact] };
+        (wired + 26 - shift) % 26
+    }
+}
+
+/// An Enigma ready to type: wheels from left to right, reflector and
+/// plugboard. Wheels beyond the three stepping ones on the right never
+/// move, as the fourth wheel of the M4.
+#[derive(Clone, Debug)]
+pub struct Machine {
+    pub(crate) wheels: Vec<Wheel>,
+    pub(crate) reflector: [u8; 26],
+    plugboard: [u8; 26],
+}
+
+impl Machine {
+    pub fn new(key: &KeySheet) -> Machine {
+        let wheels = (0..3)
+            .map(|idx| {
+                let rotor = key.rotors[idx];
+                Wheel::new(rotor.wiring(), rotor.notches(), key.rings[idx], key.positions[idx])
+            })
+            .collect();
+        Machine::from_parts(wheels, key.reflector.wiring(), &key.plugboard)
+    }
+
+    pub fn new_m4(key: &M4KeySheet) -> Machine {
+        let greek = key.greek;
+        let mut wheels = vec![Wheel::new(greek.wiring(), "", key.rings[0], key.positions[0])];
+        for idx in 0..3 {
+            let rotor = key.rotors[idx];
+            wheels.push(Wheel::new(rotor.wiring(), rotor.notches(),
+                key.rings[idx + 1], key.positions[idx + 1]));
+        }
+        Machine::from_parts(wheels, key.reflector.wiring(), &key.plugboard)
+    }
+
+    pub(crate) fn from_parts(wheels: Vec<Wheel>, reflector: &str, plugboard: &Plugboard)
+        -> Machine
+    {
+        Machine { wheels, reflector: letters(reflector), plugboard: plugboard.wiring() }
+    }
+
+    /// Window letters of the wheels, left to right.
+    pub fn positions(&self) -> Vec<u8> {
+        self.wheels.iter().map(|wheel| wheel.position).collect()
+    }
+
+    /// Turns the wheels to `positions`, left to right.
+    pub fn set_positions(&mut self, positions: &[u8]) {
+        for (wheel, position) in self.wheels.iter_mut().zip(positions) {
+            wheel.position = *position;
+        }
+    }
+
+    /// Moves the three right wheels as a key press does. The middle wheel
+    /// steps with the left one when at its notch, which makes it step on two
+    /// key presses in a row: the double step.
+    pub fn step(&mut self) {
+        let n = self.wheels.len();
+        let (left, middle, right) = (n - 3, n - 2, n - 1);
+        if self.wheels[middle].at_notch() {
+            self.wheels[left].step();
+            self.wheels[middle].step();
+        } else if self.wheels[right].at_notch() {
+            self.wheels[middle].step();
+        }
+        self.wheels[right].step();
+    }
+
+    /// Current path of `x` (0..26) through the machine, without stepping.
+    pub fn map(&self, x: u8) -> u8 {
+        let mut x = self.plugboard[x as usize];
+        for wheel in self.wheels.iter().rev() {
+            x = wheel.pass(x, true);
+        }
+        x = self.reflector[x as usize];
+        for wheel in &self.wheels {
+            x = wheel.pass(x, false);
+        }
+        self.plugboard[x as usize]
+    }
+
+    /// Steps, then enciphers `x` (0..26).
+    pub fn press(&mut self, x: u8) -> u8 {
+        self.step();
+        self.map(x)
+    }
+
+    /// Enciphers `a..=z` text. Enigma is its own inverse, so this also
+    /// deciphers.
+    pub fn encrypt(&mut self, text: &str) -> Result<String, String> {
+        if let Some(invalid) = text.chars().find(|x| !x.is_ascii_lowercase()) {
+            return Err(format!("Char '{invalid}' is out of range a..=z"));
+        }
+        Ok(text.bytes()
+            .map(|x| ALPHABET[self.press(x - b'a') as usize])
+            .collect())
+    }
+}
+
+/// Enigma I and M3, the three-wheel Army, Air Force and Navy machines.
+pub struct EnigmaM3;
+
+impl Cipher for EnigmaM3 {
+    type Key = KeySheet;
+
+    fn name(&self) -> &'static str {
+        "enigma"
+    }
+
+    fn encrypt(&self, plaintext: &str, key: &KeySheet) -> Result<String, String> {
+        Machine::new(key).encrypt(plaintext)
+    }
+
+    fn decrypt(&self, ciphertext: &str, key: &KeySheet) -> Result<String, String> {
+        Machine::new(key).encrypt(ciphertext)
+    }
+
+    fn parse_key(&self, key: &str) -> Result<KeySheet, String> {
+        KeySheet::parse(key)
+    }
+
+    fn format_key(&self, key: &KeySheet) -> String {
+        key.format()
+    }
+
+    fn generate_key<R: Rng + ?Sized>(&self, rng: &mut R) -> KeySheet {
+        KeySheet::generate(rng)
+    }
+}
+
+/// The naval M4, with a fourth wheel and a thin reflector.
+pub struct EnigmaM4;
+
+impl Cipher for EnigmaM4 {
+    type Key = M4KeySheet;
+
+    fn name(&self) -> &'static str {
+        "enigma"
+    }
+
+    fn encrypt(&self, plaintext: &str, key: &M4KeySheet) -> Result<String, String> {
+        Machine::new_m4(key).encrypt(plaintext)
+    }
+
+    fn decrypt(&self, ciphertext: &str, key: &M4KeySheet) -> Result<String, String> {
+        Machine::new_m4(key).encrypt(ciphertext)
+    }
+
+    fn parse_key(&self, key: &str) -> Result<M4KeySheet, String> {
+        M4KeySheet::parse(key)
+    }
+
+    fn format_key(&self, key: &M4KeySheet) -> String {
+        key.format()
+    }
+
+    fn generate_key<R: Rng + ?Sized>(&self, rng: &mut R) -> M4KeySheet {
+        M4KeySheet::generate(rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Published `(key, plaintext, ciphertext)` vectors: the settings every
+    /// simulator starts from, Operation Barbarossa, an Enigma I message of
+    /// 1941, and an M3 message of the Scharnhorst from 1943.
+    const VECTORS: [(&str, &str, &str); 4] = [
+        ("B:I-II-III:01-01-01:AAA:", "aaaaa", "bdzgo"),
+        ("B:I-II-III:02-02-02:AAA:", "aaaaa", "ewtyx"),
+        ("B:II-IV-V:02-21-12:BLA:AV-BS-CG-DL-FU-HZ-IN-KM-OW-RX",
+            "aufklxabteilungxvonxkurtinowaxkurtinowaxnordwestlxsebezxsebezxuaffliegerstrasze\
+             riqtungxdubrowkixdubrowkixopotschkaxopotschkaxumxeinsaqtdreinullxuhrangetretenx\
+             angriffxinfxrgtx",
+            "edpudnrgyszrcxnuytpomrmbofktbzrezkmlxlvefgueysiozveqmikubpmmylklttdeismdica\
+             gykuactcdomohwxmuuiaubstslrnbzszwnrfxwfyssxjzvijhidishprklkayupadtxqspinqma\
+             tlpifsvkdasctacdpbopvhjk"),
+        ("B:III-VI-VIII:01-08-13:UZV:AN-EZ-HK-IJ-LR-MQ-OT-PV-SW-UX",
+            "steuerejtanafjordjanstandortquaaacccvierneunneunzwofahrtzwonulsmxxscharnhorsthco",
+            "ykaenzapmschzbfocuvmrmdpycofhadzizmefxthflolpzlfggbotgoxgretdwtjiqhlmxvjwkzuastr"),
+    ];
+
+    /// M4 keys as for [`VECTORS`]: one that works as the three-wheel machine
+    /// and the start of a published naval message.
+    const M4_VECTORS: [(&str, &str, &str); 2] = [
+        ("B:Beta-I-II-III:01-01-01-01:AAAA:", "aaaaa", "bdzgo"),
+        ("B:Beta-II-IV-I:01-01-01-22:VJNA:AT-BL-DF-GJ-HM-NW-OP-QY-RZ-VX",
+            "vonvonjlooksjhffttteinseinsdreizwoyyqn",
+            "nczwvusxpnyminhzxmqxsfwxwlkjahshnmcocc"),
+    ];
+
+    #[test]
+    fn m3_matches_published_vectors() {
+        for (key, plaintext, ciphertext) in VECTORS {
+            let key = KeySheet::parse(key).unwrap();
+            assert_eq!(Machine::new(&key).encrypt(plaintext).unwrap(), ciphertext);
+            assert_eq!(Machine::new(&key).encrypt(ciphertext).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn m4_matches_published_vectors() {
+        for (key, plaintext, ciphertext) in M4_VECTORS {
+            let key = M4KeySheet::parse(key).unwrap();
+            assert_eq!(Machine::new_m4(&key).encrypt(plaintext).unwrap(), ciphertext);
+            assert_eq!(Machine::new_m4(&key).encrypt(ciphertext).unwrap(), plaintext);
+        }
+    }
+
+    #[test]
+    fn middle_wheel_double_steps() {
+        let mut machine = Machine::new(&KeySheet::parse("B:I-II-III:01-01-01:ADU:").unwrap());
+        let positions: Vec<String> = (0..3)
+            .map(|_| {
+                machine.step();
+                format_positions(&machine.positions())
+            })
+            .collect();
+        assert_eq!(positions, ["ADV", "AEW", "BFX"]);
+    }
+
+    #[test]
+    fn keys_round_trip() {
+        for key in ["B:II-IV-V:02-21-12:BLA:AV-BS-CG", "C:I-II-III:01-01-01:AAA:"] {
+            assert_eq!(KeySheet::parse(key).unwrap().format(), key);
+        }
+        let key = "B:Beta-II-IV-I:01-01-01-22:VJNA:AT-BL";
+        assert_eq!(M4KeySheet::parse(key).unwrap().format(), key);
+        assert_eq!(KeySheet::parse("b:ii-iv-v:BUL:bla:av bs").unwrap().format(),
+            "B:II-IV-V:02-21-12:BLA:AV-BS");
+    }
+
+    #[test]
+    fn bad_plugboards_are_rejected() {
+        for plugs in ["AB-C", "ABC", "A1", "AA", "AB-BC", "AB-CA"] {
+            assert!(Plugboard::parse(plugs).is_err(), "{plugs}");
+        }
+        assert_eq!(Plugboard::parse("").unwrap(), Plugboard::default());
+    }
+
+    #[test]
+    fn bad_keys_are_rejected() {
+        for key in [
+            "",
+            "B:II-IV-V:02-21-12",
+            "B:II-IV-V:02-21-12:BLA:AV:BS",
+            "D:II-IV-V:02-21-12:BLA:",
+            "B:II-IV-IX:02-21-12:BLA:",
+            "B:II-IV:02-21-12:BLA:",
+            "B:II-IV-II:02-21-12:BLA:",
+            "B:II-IV-V:00-21-12:BLA:",
+            "B:II-IV-V:02-21-27:BLA:",
+            "B:II-IV-V:02-21:BLA:",
+            "B:II-IV-V:02-21-12:BL:",
+            "B:II-IV-V:02-21-12:B1A:",
+            "B:II-IV-V:02-21-12:BLA:AV-BA",
+            "B:Beta-II-IV-V:01-02-21-12:ABLA:",
+        ] {
+            assert!(KeySheet::parse(key).is_err(), "{key}");
+        }
+        for key in ["B:II-IV-V:02-21-12:BLA:", "B:Beta-II-IV:01-02-21-12:ABLA:",
+            "B:Delta-II-IV-V:01-02-21-12:ABLA:", "B:Beta-II-IV-V:01-02-21-12:BLA:"]
+        {
+            assert!(M4KeySheet::parse(key).is_err(), "{key}");
+        }
+    }
+}
@@ -1,28 +1,118 @@
 use std::fs;
+use std::path::Path;
 
-fn generate_fitness_matrix(text: &str) -> Vec<f64> {
+use crate::normalise::Normaliser;
+
+/// Symbols of the model: letters as 0..26 and space as 26.
+pub const SYMBOLS: &[u8; 27] = b"abcdefghijklmnopqrstuvwxyz ";
+
+/// Supported n-gram orders, bigrams through pentagrams.
+pub const ORDERS: std::ops::RangeInclusive<usize> = 2..=5;
+
+/// Order used when none is asked for.
+pub const DEFAULT_ORDER: usize = 4;
+
+const MAGIC: &[u8; 4] = b"CCFM";
+const VERSION: u16 = 2;
+
+/// Language of the models built into the binary.
+pub const DEFAULT_LANGUAGE: &str = "en";
+
+/// English models of the orders small enough to build into the binary, built
+/// with `--letters-only` from The Adventures of Sherlock Holmes and Alice's
+/// Adventures in Wonderland.
+#[cfg(feature = "embedded-model")]
+const EMBEDDED_MODELS: [(usize, &[u8]); 3] = [
+    (2, include_bytes!("../models/en-2.fitness")),
+    (3, include_bytes!("../models/en-3.fitness")),
+    (4, include_bytes!("../models/en-4.fitness")),
+];
+
+/// How finely a model stores its log-probabilities.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Precision {
+    /// Rounded to `bits` (1, 2, 4, 8 or 16) bits per entry: smaller and
+    /// faster, but mediocre candidates can score like good ones.
+    Quantised(u8),
+    /// `f32` per entry.
+    Full,
+}
+
+impl Precision {
+    /// The fast default, 2 bits per entry.
+    pub const FAST: Precision = Precision::Quantised(2);
+
+    /// Value of the bits-per-entry header field, 32 meaning `f32`.
+    fn bits(&self) -> u8 {
+        match self {
+            Precision::Quantised(bits) => *bits,
+            Precision::Full => 32,
+        }
+    }
+
+    fn from_bits(bits: u8) -> Result<Precision, String> {
+        match bits {
+            1 | 2 | 4 | 8 | 16 => Ok(Precision::Quantised(bits)),
+            32 => Ok(Precision::Full),
+            _ => Err(format!("Unsupported quantisation of {bits} bits")),
+        }
+    }
+}
+
+enum Table {
+    /// Entries packed into `u64` words, lowest bits first.
+    Packed { bits: u8, words: Vec<u64> },
+    Full(Vec<f32>),
+}
+
+/// N-gram log-probabilities of one language, quantised or at full
+/// precision, along with its letter frequencies.
+///
+/// On disk (little endian): magic `CCFM`, version `u16`, n-gram order `u8`,
+/// bits per entry `u8` (32 for `f32` entries), alphabet length `u8` and the
+/// alphabet, language code length `u8` and the code, 26 letter frequencies
+/// `f64`, FNV-1a hash of the corpus `u64`, entry count `u64`, then the
+/// entries: quantised ones packed into `u64` words, lowest bits first, or
+/// `f32`s. N-grams are indexed in base 27.
+pub struct FitnessMatrix {
+    order: usize,
+    language: String,
+    unigram: [f64; 26],
+    corpus_hash: u64,
+    table: Table,
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash
+}
+
+fn generate_fitness_matrix(text: &str, order: usize) -> Vec<f64> {
     let text_bin: Vec<u8> = text.chars()
         .map(|x| if x == ' ' { 26 } else { x as u8 - 97 })
         .collect();
-    let mut quad_counts: Vec<u32> = vec![0; 32*32*32*32];
-    for i in 0..text_bin.len()-3 {
-        let idx = ((text_bin[i] as usize) << 15) +
-            ((text_bin[i+1] as usize) << 10) +
-            ((text_bin[i+2] as usize) << 5) +
-            text_bin[i+3] as usize;
-        quad_counts[idx] += 1;
-    }
-    let total = text_bin.len() - 3;
-    
-    let min_value = *quad_counts.iter()
+    let size = SYMBOLS.len().pow(order as u32);
+    let mut counts: Vec<u32> = vec![0; size];
+    for window in text_bin.windows(order) {
+        let idx = window.iter()
+            .fold(0, |idx, x| idx * SYMBOLS.len() + *x as usize);
+        counts[idx] += 1;
+    }
+    let total = text_bin.len() + 1 - order;
+
+    let min_value = *counts.iter()
         .filter(|x| **x != 0)
         .min()
         .unwrap();
     let offset = (min_value as f64 / 10.0 / total as f64).log10();
 
-    let mut matrix: Vec<f64> = vec![0.0; 32*32*32*32];
+    let mut matrix: Vec<f64> = vec![0.0; size];
     let mut norm = 0.0;
-    for (idx, value) in quad_counts.into_iter().enumerate() {
+    for (idx, value) in counts.into_iter().enumerate() {
         if value == 0 { continue; }
         let prop = value as f64 / total as f64;
         let new_value = prop.log10() - offset;
@@ -34,49 +124,340 @@ fn generate_fitness_matrix(text: &str) -> Vec<f64> {
     matrix
 }
 
-pub fn make_fitness_matrix_file() {
-    let file_content = fs::read_to_string("war_and_peace_processed.txt").unwrap();
-    let matrix = generate_fitness_matrix(&file_content);
-    println!("{}", matrix.len());
-    let _ = fs::write(format!("fitness_matrix.data"),
-        matrix.into_iter().map(|x| x.to_string())
-        .collect::<Vec<String>>().join("\n"));
+impl FitnessMatrix {
+    /// Builds a full precision model of n-grams of length `order` from
+    /// `corpus` (only `a..=z` and spaces) written in `language`.
+    pub fn from_corpus(corpus: &str, order: usize, language: &str)
+        -> Result<FitnessMatrix, String>
+    {
+        if !ORDERS.contains(&order) {
+            return Err(format!("Unsupported n-gram order {order}"));
+        }
+        if let Some(invalid) = corpus.chars().find(|x| *x != ' ' && !x.is_ascii_lowercase()) {
+            return Err(format!("Char '{invalid}' is out of range a..=z"));
+        }
+        if corpus.len() < order {
+            return Err(format!("Corpus must be at least {order} chars long"));
+        }
+        if language.is_empty() || language.len() > u8::MAX as usize {
+            return Err(format!("Invalid language code '{language}'"));
+        }
+        let mut counts = [0usize; 26];
+        corpus.bytes()
+            .filter(|x| *x != b' ')
+            .for_each(|x| counts[(x - b'a') as usize] += 1);
+        let letters = counts.iter().sum::<usize>().max(1) as f64;
+        let unigram = counts.map(|x| x as f64 / letters);
+
+        let matrix = generate_fitness_matrix(corpus, order);
+        Ok(FitnessMatrix {
+            order,
+            language: language.to_string(),
+            unigram,
+            corpus_hash: fnv1a(corpus.as_bytes()),
+            table: Table::Full(matrix.into_iter().map(|x| x as f32).collect()),
+        })
+    }
+
+    /// Fitness represented by one quantisation level.
+    fn step(bits: u8) -> f64 {
+        100.0 / (1u64 << (bits - 1)) as f64
+    }
+
+    /// Returns this model at `precision`. Precision lost by quantising
+    /// cannot be recovered, so only full models convert freely.
+    pub fn with_precision(self, precision: Precision) -> Result<FitnessMatrix, String> {
+        if precision == self.precision() {
+            return Ok(self);
+        }
+        let Table::Full(values) = &self.table else {
+            return Err(format!("Cannot convert a {}-bit model", self.precision().bits()));
+        };
+        let Precision::Quantised(bits) = precision else {
+            unreachable!("Full models are returned unchanged");
+        };
+        Precision::from_bits(bits)?;
+        let per_word = 64 / bits as usize;
+        let max = (1u64 << bits) - 1;
+        let step = Self::step(bits);
+        let mut words = vec![0u64; values.len().div_ceil(per_word)];
+        for (idx, value) in values.iter().enumerate() {
+            let quantised = ((*value as f64 / step).round() as u64).min(max);
+            words[idx / per_word] |= quantised << (idx % per_word * bits as usize);
+        }
+        Ok(FitnessMatrix {
+            table: Table::Packed { bits, words },
+            ..self
+        })
+    }
+
+    pub fn order(&self) -> usize {
+        self.order
+    }
+
+    pub fn precision(&self) -> Precision {
+        match self.table {
+            Table::Packed { bits, .. } => Precision::Quantised(bits),
+            Table::Full(_) => Precision::Full,
+        }
+    }
+
+    pub fn language(&self) -> &str {
+        &self.language
+    }
+
+    /// Frequencies of `a..=z` in the corpus.
+    pub fn unigram(&self) -> &[f64; 26] {
+        &self.unigram
+    }
+
+    pub fn corpus_hash(&self) -> u64 {
+        self.corpus_hash
+    }
+
+    /// Fitness of the single n-gram whose symbols, read as base 27 digits,
+    /// give `idx`.
+    pub fn ngram_fitness(&self, idx: usize) -> f64 {
+        match &self.table {
+            Table::Packed { bits, words } => {
+                let bits = *bits as usize;
+                let per_word = 64 / bits;
+                let mask = u64::MAX >> (64 - bits);
+                let level = words[idx / per_word] >> (idx % per_word * bits) & mask;
+                level as f64 * FitnessMatrix::step(bits as u8)
+            },
+            Table::Full(values) => values[idx] as f64,
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(64);
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&VERSION.to_le_bytes());
+        bytes.push(self.order as u8);
+        bytes.push(self.precision().bits());
+        bytes.push(SYMBOLS.len() as u8);
+        bytes.extend_from_slice(SYMBOLS);
+        bytes.push(self.language.len() as u8);
+        bytes.extend_from_slice(self.language.as_bytes());
+        for freq in &self.unigram {
+            bytes.extend_from_slice(&freq.to_le_bytes());
+        }
+        bytes.extend_from_slice(&self.corpus_hash.to_le_bytes());
+        let entries = SYMBOLS.len().pow(self.order as u32) as u64;
+        bytes.extend_from_slice(&entries.to_le_bytes());
+        match &self.table {
+            Table::Packed { words, .. } => words.iter()
+                .for_each(|x| bytes.extend_from_slice(&x.to_le_bytes())),
+            Table::Full(values) => values.iter()
+                .for_each(|x| bytes.extend_from_slice(&x.to_le_bytes())),
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<FitnessMatrix, String> {
+        let mut pos = 0;
+        let mut take = |len: usize| -> Result<&[u8], String> {
+            let slice = bytes.get(pos..pos+len)
+                .ok_or("Fitness model is truncated".to_string())?;
+            pos += len;
+            Ok(slice)
+        };
+        if take(4)? != MAGIC {
+            return Err("Not a fitness model file".to_string());
+        }
+        let version = u16::from_le_bytes(take(2)?.try_into().unwrap());
+        if version != VERSION {
+            return Err(format!("Unsupported fitness model version {version}"));
+        }
+        let order = take(1)?[0] as usize;
+        if !ORDERS.contains(&order) {
+            return Err(format!("Unsupported n-gram order {order}"));
+        }
+        let precision = Precision::from_bits(take(1)?[0])?;
+        let alphabet_len = take(1)?[0] as usize;
+        if take(alphabet_len)? != SYMBOLS {
+            return Err("Fitness model uses an unsupported alphabet".to_string());
+        }
+        let language_len = take(1)?[0] as usize;
+        let language = String::from_utf8(take(language_len)?.to_vec())
+            .map_err(|_| "Fitness model has an invalid language code".to_string())?;
+        let mut unigram = [0.0; 26];
+        for freq in unigram.iter_mut() {
+            *freq = f64::from_le_bytes(take(8)?.try_into().unwrap());
+        }
+        let corpus_hash = u64::from_le_bytes(take(8)?.try_into().unwrap());
+        let entries = u64::from_le_bytes(take(8)?.try_into().unwrap()) as usize;
+        if entries != SYMBOLS.len().pow(order as u32) {
+            return Err(format!("Fitness model has {entries} entries for order {order}"));
+        }
+        let table = match precision {
+            Precision::Quantised(bits) => {
+                let per_word = 64 / bits as usize;
+                let words = take(entries.div_ceil(per_word) * 8)?
+                    .chunks_exact(8)
+                    .map(|x| u64::from_le_bytes(x.try_into().unwrap()))
+                    .collect();
+                Table::Packed { bits, words }
+            },
+            Precision::Full => Table::Full(take(entries * 4)?
+                .chunks_exact(4)
+                .map(|x| f32::from_le_bytes(x.try_into().unwrap()))
+                .collect()),
+        };
+        Ok(FitnessMatrix { order, language, unigram, corpus_hash, table })
+    }
+}
+
+/// Builds a model from the UTF-8 corpus at `corpus_path`, after passing it
+/// through `normaliser`, and writes it to `path`.
+pub fn make_fitness_matrix_file(corpus_path: &str, path: &str, order: usize,
+    language: &str, precision: Precision, normaliser: &Normaliser) -> Result<(), String>
+{
+    let corpus = fs::read_to_string(corpus_path)
+        .map_err(|e| format!("Cannot read {corpus_path}: {e}"))?;
+    let matrix = FitnessMatrix::from_corpus(&normaliser.normalise(&corpus), order, language)?
+        .with_precision(precision)?;
+    fs::write(path, matrix.to_bytes())
+        .map_err(|e| format!("Cannot write {path}: {e}"))
 }
 
-pub struct FitnessMatrix {
-    // 32768 = 32^4 / 32
-    matrix: [u64; 32768],
+pub fn load_fitness_matrix(path: &str) -> Result<FitnessMatrix, String> {
+    let bytes = fs::read(path)
+        .map_err(|e| format!("Cannot read {path}: {e}"))?;
+    FitnessMatrix::from_bytes(&bytes)
 }
 
-pub fn generate_fitness_matrix_from_file() -> FitnessMatrix {
-    let matrix = fs::read_to_string("fitness_matrix.data")
-        .unwrap()
-        .split("\n")
-        .map(|x| (x.parse::<f64>().unwrap()/100.0*2.0).round() as u8)
-        .collect::<Vec<u8>>();
-    let mut bit_matrix: [u64; 32768] = [0; 32768];
-    let mut bit_matrix_idx = 0;
-    for i in (0..matrix.len()).step_by(32) {
-        let mut bit: u64 = 0;
-        for j in 0..32 {
-            bit |= (matrix[i+j] as u64) << (j*2);
-        }
-        bit_matrix[bit_matrix_idx] = bit;
-        bit_matrix_idx += 1;
-    }
-    FitnessMatrix { matrix: bit_matrix }
+/// File name of the model of `language` and `order` inside a model directory.
+pub fn model_file_name(language: &str, order: usize) -> String {
+    format!("{language}-{order}.fitness")
 }
 
-pub fn compute_fitness(text: &Vec<u8>, matrix: &FitnessMatrix) -> f64 {
-    let mut idx: usize = ((text[0] as usize) << 10) + 
-        ((text[1] as usize) << 5) +
-        text[2] as usize;
-    let mut fitness = 0;
-    for text_char in &text[3..] {
-        idx = ((idx & 0x7FFF) << 5) | (*text_char as usize);
-        //                         *32         mod 32
-        let val = matrix.matrix[idx>>5] >> ((idx&0x1F) << 1) & 0x03;
-        fitness += val as u64;
-    }
-    fitness as f64 / 2.0 * 100.0 / (text.len()-3) as f64
+/// The model of `language` and `order` at `precision`: built in for
+/// English, otherwise read from `model_dir`.
+pub fn language_fitness_matrix(language: &str, order: usize, precision: Precision,
+    model_dir: &str) -> Result<FitnessMatrix, String>
+{
+    if cfg!(feature = "embedded-model") && language == DEFAULT_LANGUAGE {
+        return default_fitness_matrix(order, precision);
+    }
+    let path = Path::new(model_dir).join(model_file_name(language, order));
+    let matrix = load_fitness_matrix(&path.to_string_lossy())?;
+    if matrix.language() != language {
+        return Err(format!("{} is a model of language '{}'",
+            path.display(), matrix.language()));
+    }
+    if matrix.order() != order {
+        return Err(format!("{} has n-gram order {}", path.display(), matrix.order()));
+    }
+    matrix.with_precision(precision)
+}
+
+/// The English model of `order` built into the binary, at `precision`.
+#[cfg(feature = "embedded-model")]
+pub fn default_fitness_matrix(order: usize, precision: Precision)
+    -> Result<FitnessMatrix, String>
+{
+    let (_, bytes) = EMBEDDED_MODELS.iter()
+        .find(|(model_order, _)| *model_order == order)
+        .ok_or(format!("No built-in model of order {order}, build one with build-model"))?;
+    FitnessMatrix::from_bytes(bytes)?.with_precision(precision)
+}
+
+/// Without the embedded models, falls back to `fitness_matrix.data` in the
+/// working directory.
+#[cfg(not(feature = "embedded-model"))]
+pub fn default_fitness_matrix(order: usize, precision: Precision)
+    -> Result<FitnessMatrix, String>
+{
+    let matrix = load_fitness_matrix("fitness_matrix.data")?;
+    if matrix.order() != order {
+        return Err(format!("fitness_matrix.data has order {}, not {order}", matrix.order()));
+    }
+    matrix.with_precision(precision)
+}
+
+/// Scores `text` (letters as 0..26, space as 26) against `matrix`.
+pub fn compute_fitness(text: &[u8], matrix: &FitnessMatrix) -> f64 {
+    let order = matrix.order;
+    if text.len() < order {
+        return 0.0;
+    }
+    let symbols = SYMBOLS.len();
+    // Weight of the first char of an n-gram in its index.
+    let lead = symbols.pow(order as u32 - 1);
+    let count = (text.len() + 1 - order) as f64;
+
+    let mut idx = text[..order-1].iter()
+        .fold(0, |idx, x| idx * symbols + *x as usize);
+    match &matrix.table {
+        Table::Packed { bits, words } => {
+            let bits = *bits as usize;
+            let per_word = 64 / bits;
+            let shift = per_word.trailing_zeros();
+            let mask = u64::MAX >> (64 - bits);
+            let mut fitness = 0;
+            for (pos, text_char) in text[order-1..].iter().enumerate() {
+                idx = idx * symbols + *text_char as usize;
+                fitness += words[idx >> shift] >> ((idx & (per_word-1)) * bits) & mask;
+                idx -= text[pos] as usize * lead;
+            }
+            fitness as f64 * FitnessMatrix::step(bits as u8) / count
+        },
+        Table::Full(values) => {
+            let mut fitness = 0.0;
+            for (pos, text_char) in text[order-1..].iter().enumerate() {
+                idx = idx * symbols + *text_char as usize;
+                fitness += values[idx] as f64;
+                idx -= text[pos] as usize * lead;
+            }
+            fitness / count
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CORPUS: &str = "the quick brown fox jumps over the lazy dog and the cat";
+
+    fn assert_same(matrix: &FitnessMatrix, read: &FitnessMatrix) {
+        assert_eq!(read.order(), matrix.order());
+        assert_eq!(read.precision(), matrix.precision());
+        assert_eq!(read.language(), matrix.language());
+        assert_eq!(read.unigram(), matrix.unigram());
+        assert_eq!(read.corpus_hash(), matrix.corpus_hash());
+        for idx in 0..SYMBOLS.len().pow(matrix.order() as u32) {
+            assert_eq!(read.ngram_fitness(idx), matrix.ngram_fitness(idx));
+        }
+    }
+
+    #[test]
+    fn full_model_round_trips() {
+        let matrix = FitnessMatrix::from_corpus(CORPUS, 3, "en").unwrap();
+        assert_same(&matrix, &FitnessMatrix::from_bytes(&matrix.to_bytes()).unwrap());
+    }
+
+    #[test]
+    fn quantised_models_round_trip() {
+        for bits in [1, 2, 4, 8, 16] {
+            let matrix = FitnessMatrix::from_corpus(CORPUS, 2, "xx").unwrap()
+                .with_precision(Precision::Quantised(bits))
+                .unwrap();
+            assert_same(&matrix, &FitnessMatrix::from_bytes(&matrix.to_bytes()).unwrap());
+        }
+    }
+
+    #[test]
+    fn damaged_models_are_rejected() {
+        let bytes = FitnessMatrix::from_corpus(CORPUS, 2, "en").unwrap().to_bytes();
+        assert!(FitnessMatrix::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let mut bad_magic = bytes.clone();
+        bad_magic[0] = b'X';
+        assert!(FitnessMatrix::from_bytes(&bad_magic).is_err());
+        let mut bad_version = bytes;
+        bad_version[4] = 99;
+        assert!(FitnessMatrix::from_bytes(&bad_version).is_err());
+    }
 }
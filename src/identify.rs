@@ -0,0 +1,207 @@
+use std::collections::HashSet;
+
+use crate::caesar::chi_sqr;
+use crate::english::{ALPHABET, ENGLISH_FREQ};
+use crate::normalise::Layout;
+use crate::stats::{ENGLISH_IC, RANDOM_IC, index_of_coincidence, periodic_ic, to_letters};
+
+/// Longest key period looked for.
+pub const MAX_PERIOD: usize = 20;
+
+/// Fewest letters the statistics mean anything for.
+pub const MIN_LENGTH: usize = 20;
+
+/// Statistics of a ciphertext that tell cipher families apart.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Features {
+    /// Number of letters.
+    pub length: usize,
+    /// Number of distinct letters.
+    pub alphabet_size: usize,
+    pub ic: f64,
+    /// Chi-squared of the letters against English, per letter.
+    pub chi_squared: f64,
+    /// Chi-squared per letter after the best Caesar shift.
+    pub shifted_chi_squared: f64,
+    /// Period above 1 with the highest average column IC, and that IC.
+    pub period: usize,
+    pub periodic_ic: f64,
+    /// Fraction of letters equal to the next one.
+    pub doubled: f64,
+    /// Doubled letters starting at an even position, which digraphic
+    /// ciphers such as Playfair never produce.
+    pub doubled_in_pairs: usize,
+    pub even_length: bool,
+}
+
+impl Features {
+    pub fn new(ciphertext: &str) -> Result<Features, String> {
+        let layout = Layout::new(ciphertext);
+        let letters = layout.letters();
+        let length = letters.len();
+        if length < MIN_LENGTH {
+            return Err(format!("Ciphertext must have at least {MIN_LENGTH} letters"));
+        }
+        let alphabet_size = letters.chars().collect::<HashSet<char>>().len();
+        let text = to_letters(letters);
+        let chi_squared = chi_sqr(letters, &ENGLISH_FREQ) / length as f64;
+        let shifted_chi_squared = (0..26)
+            .map(|shift| {
+                let shifted: String = text.iter()
+                    .map(|x| ALPHABET[(*x as usize + shift) % 26])
+                    .collect();
+                chi_sqr(&shifted, &ENGLISH_FREQ) / length as f64
+            })
+            .fold(f64::INFINITY, f64::min);
+        // Multiples of the true period score as well, so the smallest period
+        // close to the best one is taken. Columns need some letters for
+        // their IC to mean anything.
+        let periods: Vec<(usize, f64)> = (2..=MAX_PERIOD.min(length / 10))
+            .map(|period| (period, periodic_ic(&text, period)))
+            .collect();
+        let best = periods.iter().map(|(_, ic)| *ic).fold(0.0, f64::max);
+        let (period, periodic_ic) = periods.into_iter()
+            .find(|(_, ic)| *ic >= best * 0.9)
+            .unwrap_or((1, 0.0));
+        let doubles: Vec<usize> = text.windows(2)
+            .enumerate()
+            .filter(|(_, pair)| pair[0] == pair[1])
+            .map(|(idx, _)| idx)
+            .collect();
+        Ok(Features {
+            length,
+            alphabet_size,
+            ic: index_of_coincidence(&text),
+            chi_squared,
+            shifted_chi_squared,
+            period,
+            periodic_ic,
+            doubled: doubles.len() as f64 / length as f64,
+            doubled_in_pairs: doubles.iter().filter(|idx| *idx % 2 == 0).count(),
+            even_length: length.is_multiple_of(2),
+        })
+    }
+}
+
+/// Groups of ciphers that [`identify`] tells apart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Family {
+    /// Letters moved around but not changed.
+    Transposition,
+    Caesar,
+    Substitution,
+    /// Periodic polyalphabetic ciphers: Vigenère, Beaufort and kin.
+    Vigenere,
+    /// Polyalphabetic ciphers without a period, such as autokey, running key
+    /// and Enigma.
+    Aperiodic,
+    /// Digraphic ciphers such as Playfair.
+    Playfair,
+}
+
+impl Family {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Family::Transposition => "transposition",
+            Family::Caesar => "caesar",
+            Family::Substitution => "substitution",
+            Family::Vigenere => "vigenere",
+            Family::Aperiodic => "aperiodic polyalphabetic",
+            Family::Playfair => "playfair",
+        }
+    }
+}
+
+/// Likeness of `value` to `target`, 1 when equal and falling off over `width`.
+fn near(value: f64, target: f64, width: f64) -> f64 {
+    (-((value - target) / width).powi(2)).exp()
+}
+
+/// Ranks cipher families by how well they explain `features`, returning
+/// `(family, confidence)` pairs, best first, with confidences summing to 1.
+pub fn rank_families(features: &Features) -> Vec<(Family, f64)> {
+    // Statistics of short texts spread further from their expected values.
+    let noise = (100.0 / features.length as f64).sqrt().max(1.0);
+    // Repetitive text may have an IC above English, still from one alphabet.
+    let english_ic = near(features.ic.min(ENGLISH_IC), ENGLISH_IC, 0.007 * noise);
+    let flat_ic = near(features.ic, RANDOM_IC, 0.005 * noise);
+    let digraphic_ic = near(features.ic, 0.052, 0.006 * noise);
+    // Chi-squared per letter of English text falls like 1 / length: on
+    // samples of 100 to 300 letters 99% stay under about 60 / length, while
+    // shifting a substitution's letters seldom brings it under 0.8.
+    let fit_width = 0.4 + 45.0 / features.length as f64;
+    let english_fit = near(features.chi_squared, 0.0, fit_width);
+    let shift_fit = near(features.shifted_chi_squared, 0.0, fit_width);
+    let column_noise = (100.0 * features.period as f64 / features.length as f64).sqrt().max(1.0);
+    let (periodic, aperiodic) = if features.period > 1 {
+        (near(features.periodic_ic, ENGLISH_IC, 0.008 * column_noise),
+            near(features.periodic_ic, RANDOM_IC, 0.008 * column_noise))
+    } else {
+        (0.0, 1.0)
+    };
+    let digraphic = if features.even_length { 1.0 } else { 0.05 }
+        * if features.doubled_in_pairs == 0 { 1.0 } else { 0.05 }
+        * if features.alphabet_size <= 25 { 1.0 } else { 0.2 };
+
+    let mut ranked = vec![
+        (Family::Transposition, english_ic * english_fit),
+        (Family::Caesar, english_ic * (1.0 - english_fit) * shift_fit),
+        (Family::Substitution, english_ic * (1.0 - shift_fit)),
+        (Family::Vigenere, (1.0 - english_ic) * periodic),
+        (Family::Aperiodic, flat_ic * aperiodic),
+        (Family::Playfair, digraphic_ic * digraphic),
+    ];
+    let total: f64 = ranked.iter().map(|(_, score)| score).sum();
+    for (_, score) in ranked.iter_mut() {
+        *score = if total > 0.0 { *score / total } else { 1.0 / 6.0 };
+    }
+    ranked.sort_by(|(_, s1), (_, s2)| s2.partial_cmp(s1).unwrap());
+    ranked
+}
+
+/// Computes the features of `ciphertext` and ranks the cipher families.
+pub fn identify(ciphertext: &str) -> Result<(Features, Vec<(Family, f64)>), String> {
+    let features = Features::new(ciphertext)?;
+    let ranked = rank_families(&features);
+    Ok((features, ranked))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn one_alphabet(length: usize, chi_squared: f64, shifted_chi_squared: f64) -> Features {
+        Features {
+            length,
+            alphabet_size: 24,
+            ic: ENGLISH_IC,
+            chi_squared,
+            shifted_chi_squared,
+            period: 1,
+            periodic_ic: 0.0,
+            doubled: 0.03,
+            doubled_in_pairs: 3,
+            even_length: false,
+        }
+    }
+
+    #[test]
+    fn short_caesar_text_ranks_as_caesar() {
+        let ranked = rank_families(&one_alphabet(217, 1.9, 0.29));
+        assert_eq!(ranked[0].0, Family::Caesar);
+    }
+
+    #[test]
+    fn substitution_ranks_as_substitution() {
+        for length in [100, 217, 1000] {
+            let ranked = rank_families(&one_alphabet(length, 2.0, 0.85));
+            assert_eq!(ranked[0].0, Family::Substitution);
+        }
+    }
+
+    #[test]
+    fn alphabet_size_counts_letters_only() {
+        let features = Features::new("Abc, abc; ABC! def? 1234 ghi jkl mno.").unwrap();
+        assert_eq!(features.alphabet_size, 15);
+    }
+}
@@ -0,0 +1,19 @@
+//! Cracking classical ciphers using hill climb and quadgram fitness function.
+
+pub mod auto;
+pub mod autokey;
+pub mod bombe;
+pub mod caesar;
+pub mod cipher;
+pub mod enigma;
+pub mod enigma_machine;
+pub mod english;
+pub mod fitness;
+pub mod identify;
+pub mod normalise;
+pub mod running_key;
+pub mod search;
+pub mod segment;
+pub mod stats;
+pub mod substitution;
+pub mod vigenere;
@@ -1,33 +1,681 @@
+use std::collections::HashMap;
 use std::env;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process::ExitCode;
+use std::time::Duration;
 
-mod caesar;
-mod enigma;
-mod english;
-mod fitness;
-mod vigenere;
-mod substitution;
-
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    let error_msg = "Invalid argument (use help for help)";
-    let help_msg = "Usage: ciphers <arguments>
-arguments: 
-    help 
-    caesar 
-    enigma
-    vigenere 
-    substitution
-    make_fitness_file";
-    match args.len() {
-        1 => println!("{}", error_msg),
-        _ => match args[1].as_str() {
-            "help" => println!("{}", help_msg),
-            "caesar" => caesar::main(),
-            "enigma" => enigma::main(),
-            "vigenere" => vigenere::main(),
-            "substitution" => substitution::main(&args[1..]),
-            "make_fitness_file" => fitness::make_fitness_matrix_file(),
-            _ => println!("{}", error_msg),
+use rand::rngs::StdRng;
+use rand::{thread_rng, Rng, SeedableRng};
+
+use crack_ciphers::autokey::Autokey;
+use crack_ciphers::caesar::Caesar;
+use crack_ciphers::cipher::{Cipher, reverse_permutation};
+use crack_ciphers::enigma_machine::{EnigmaM3, EnigmaM4, Rotor};
+use crack_ciphers::fitness::{FitnessMatrix, Precision};
+use crack_ciphers::normalise::{CharPolicy, Layout, Normaliser};
+use crack_ciphers::running_key::RunningKey;
+use crack_ciphers::search::{Cooling, Schedule, SearchOptions, Strategy};
+use crack_ciphers::segment::WordModel;
+use crack_ciphers::substitution::Substitution;
+use crack_ciphers::vigenere::{Beaufort, Gronsfeld, Variant, VariantBeaufort, Vigenere};
+use crack_ciphers::{
+    auto, autokey, bombe, caesar, enigma, fitness, identify, running_key, segment, substitution,
+    vigenere,
+};
+
+const USAGE: &str = "Usage: crack_ciphers <command> [options]
+commands:
+    help
+    <cipher> encrypt [--key KEY] [--input FILE|-] [--output FILE|-]
+    <cipher> decrypt --key KEY [--input FILE|-] [--output FILE|-]
+    <cipher> crack [--segment] [--input FILE|-] [--output FILE|-]
+    identify [--input FILE|-] [--output FILE|-]
+    bombe --crib TEXT [--offset N] [--rotors LIST] [--input FILE|-] [--output FILE|-]
+    crib --crib TEXT [--rotors LIST] [--max-positions N] [--positions-only]
+         [--input FILE|-] [--output FILE|-]
+    auto [--budget SECONDS] [--segment] [--input FILE|-] [--output FILE|-]
+    build-model --corpus FILE [--lang CODE] [--order N] [--precision P] [--output FILE]
+                [--words FILE] [--dictionary FILE] [--digits POLICY] [--punctuation POLICY]
+                [--letters-only]
+ciphers:
+    caesar, vigenere, beaufort, variant-beaufort, gronsfeld, autokey, running-key,
+    substitution, enigma
+    Only letters are changed, case, spacing and punctuation are kept.
+    Cracking beaufort, variant-beaufort or gronsfeld tries all three.
+    Cracking enigma scores with the bigram and trigram models of --lang.
+    Enigma keys are reflector:wheels:rings:positions:plugs, as
+    B:II-IV-V:02-21-12:BLA:AV-BS-CG-DL-FU-HZ-IN-KM-OW-RX, or for the M4 with a
+    thin reflector and a Greek wheel B:Beta-II-IV-I:01-01-01-22:VJNA:AT-BL-DF.
+options:
+    --key KEY               key as printed by encrypt and crack
+    --key-length NUMBER     length of generated keys (default 8, 1000 for running-key)
+    --input FILE|-          read text from FILE, or stdin (default)
+    --output FILE|-         write text to FILE, or stdout (default)
+    --model FILE            fitness model for crack (default: built-in English)
+    --order 2..5            n-gram order of the fitness model (default 4)
+    --precision full|BITS   fitness model precision, full or 1, 2, 4, 8 or 16 bits
+                            per entry (default 2 for crack, full for models)
+    --lang CODE             language of the fitness model (default en, built in)
+    --model-dir DIR         directory of models of other languages (default models)
+    --corpus FILE           UTF-8 text to build a model from
+    --segment               put spaces back between the words of cracked text
+    --words FILE            word model for --segment, written by build-model next to
+                            the fitness model by default (default: built-in English)
+    --dictionary FILE       word list, one per line, whose words the corpus lacks are
+                            added to the word model once each
+    --digits POLICY         remove, space or spell digits of the corpus (default space)
+    --punctuation POLICY    remove or space punctuation of the corpus (default space)
+    --letters-only          drop spaces and punctuation from the output, or from the
+                            corpus of a fitness model, like ciphertext is cracked
+    --method search|columns|polish
+                            how vigenere and kin find the key: hill climbs from random
+                            keys (default), chi-squared of each column, or chi-squared
+                            followed by a hill climb
+    --machine m3|m4         enigma with three wheels, as the Enigma I and M3
+                            (default), or the four of the naval M4
+    --rotors LIST           rotors enigma crack and bombe try in each place, as I-II-III-IV-V-VI
+                            (default I-II-III-IV-V, those of the Enigma I)
+    --crib TEXT             plaintext known to be at --offset, for bombe, or somewhere,
+                            for crib
+    --offset N              letter of the ciphertext the crib starts at (default 0)
+    --max-positions N       crib positions crib runs the bombe at, in order, stopping at
+                            the first key that deciphers the crib (default all)
+    --positions-only        list the positions the crib may stand at and stop
+    --budget SECONDS        time auto shares between likely ciphers (default 60)
+    --strategy hill|anneal|mixed
+    --temperature START:END annealing temperatures (default 2:0.01)
+    --steps NUMBER          annealing steps of each restart (default 20000)
+    --cooling linear|geometric
+                            how the annealing temperature falls (default geometric)
+    --threads NUMBER
+    --seed NUMBER";
+
+const VALUE_OPTIONS: [&str; 27] = [
+    "--key", "--key-length", "--input", "--output", "--strategy",
+    "--threads", "--seed", "--model", "--order", "--precision",
+    "--lang", "--model-dir", "--corpus", "--digits", "--punctuation",
+    "--words", "--budget", "--method", "--machine", "--rotors",
+    "--crib", "--offset", "--temperature", "--steps", "--cooling",
+    "--max-positions", "--dictionary",
+];
+
+const FLAG_OPTIONS: [&str; 3] = ["--letters-only", "--positions-only", "--segment"];
+
+enum CliError {
+    /// The command line itself is wrong.
+    Usage(String),
+    /// The command was understood but could not be carried out.
+    Failed(String),
+}
+
+type CliResult<T> = Result<T, CliError>;
+
+struct Args {
+    positional: Vec<String>,
+    options: HashMap<String, String>,
+}
+
+impl Args {
+    fn parse(args: &[String]) -> CliResult<Args> {
+        let mut positional = Vec::new();
+        let mut options = HashMap::new();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            if arg == "-" || !arg.starts_with("--") {
+                positional.push(arg.clone());
+                continue;
+            }
+            if arg == "--help" {
+                positional.insert(0, "help".to_string());
+                continue;
+            }
+            if FLAG_OPTIONS.contains(&arg.as_str()) {
+                options.insert(arg.clone(), String::new());
+                continue;
+            }
+            if !VALUE_OPTIONS.contains(&arg.as_str()) {
+                return Err(CliError::Usage(format!("Unknown option {arg}")));
+            }
+            let value = iter.next()
+                .ok_or(CliError::Usage(format!("Missing value for {arg}")))?;
+            options.insert(arg.clone(), value.clone());
+        }
+        Ok(Args { positional, options })
+    }
+
+    fn get(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(|x| x.as_str())
+    }
+
+    fn flag(&self, name: &str) -> bool {
+        self.options.contains_key(name)
+    }
+
+    fn parse_number<T: std::str::FromStr>(&self, name: &str, default: T) -> CliResult<T> {
+        match self.get(name) {
+            None => Ok(default),
+            Some(value) => value.parse::<T>()
+                .map_err(|_| CliError::Usage(format!("Invalid value for {name}: {value}"))),
+        }
+    }
+}
+
+/// Restarts of a search between progress reports.
+const REPORT_EVERY: usize = 100;
+
+/// Search settings given on the command line.
+struct Settings {
+    strategies: Vec<Strategy>,
+    threads: usize,
+    seed: u64,
+}
+
+impl Settings {
+    fn from_args(args: &Args) -> CliResult<Settings> {
+        let annealing = Strategy::Annealing(parse_schedule(args)?);
+        let strategies = match args.get("--strategy").unwrap_or("hill") {
+            "hill" => vec![Strategy::HillClimb],
+            "anneal" => vec![annealing],
+            "mixed" => vec![Strategy::HillClimb, annealing],
+            name => return Err(CliError::Usage(
+                format!("Invalid strategy {name} (use hill, anneal or mixed)"))),
+        };
+        let threads = args.parse_number("--threads", 1)?;
+        if threads == 0 {
+            return Err(CliError::Usage("--threads must be at least 1".to_string()));
+        }
+        let seed = args.parse_number("--seed", thread_rng().gen())?;
+        Ok(Settings { strategies, threads, seed })
+    }
+
+    /// `defaults` with these settings, reporting progress on stderr.
+    fn options(&self, defaults: SearchOptions) -> SearchOptions {
+        SearchOptions {
+            report_every: REPORT_EVERY,
+            strategies: self.strategies.clone(),
+            threads: self.threads,
+            seed: self.seed,
+            ..defaults
+        }
+    }
+
+    fn rng(&self) -> StdRng {
+        StdRng::seed_from_u64(self.seed)
+    }
+}
+
+/// Annealing schedule of `--temperature`, `--steps` and `--cooling`.
+fn parse_schedule(args: &Args) -> CliResult<Schedule> {
+    let defaults = Schedule::default();
+    let (start_temperature, end_temperature) = match args.get("--temperature") {
+        None => (defaults.start_temperature, defaults.end_temperature),
+        Some(value) => value.split_once(':')
+            .and_then(|(start, end)| Some((start.parse::<f64>().ok()?, end.parse::<f64>().ok()?)))
+            .filter(|(start, end)| *start > 0.0 && *end > 0.0)
+            .ok_or(CliError::Usage(format!("Invalid value for --temperature: {value} \
+                (use START:END, both above 0)")))?,
+    };
+    let steps = args.parse_number("--steps", defaults.steps)?;
+    if steps == 0 {
+        return Err(CliError::Usage("--steps must be at least 1".to_string()));
+    }
+    let cooling = match args.get("--cooling") {
+        None => defaults.cooling,
+        Some("linear") => Cooling::Linear,
+        Some("geometric") => Cooling::Geometric,
+        Some(name) => return Err(CliError::Usage(
+            format!("Invalid cooling {name} (use linear or geometric)"))),
+    };
+    Ok(Schedule { start_temperature, end_temperature, steps, cooling })
+}
+
+fn read_input(args: &Args) -> CliResult<String> {
+    let text = match args.get("--input") {
+        None | Some("-") => {
+            let mut text = String::new();
+            io::stdin().read_to_string(&mut text)
+                .map_err(|e| CliError::Failed(format!("Cannot read stdin: {e}")))?;
+            text
+        },
+        Some(path) => fs::read_to_string(path)
+            .map_err(|e| CliError::Failed(format!("Cannot read {path}: {e}")))?,
+    };
+    // Only the newline ending the last line is dropped, write_output adds
+    // it back.
+    let text = text.strip_suffix("\r\n").or(text.strip_suffix('\n')).unwrap_or(&text);
+    Ok(text.to_string())
+}
+
+fn write_output(args: &Args, text: &str) -> CliResult<()> {
+    match args.get("--output") {
+        None | Some("-") => writeln!(io::stdout(), "{text}")
+            .map_err(|e| CliError::Failed(format!("Cannot write stdout: {e}"))),
+        Some(path) => fs::write(path, format!("{text}\n"))
+            .map_err(|e| CliError::Failed(format!("Cannot write {path}: {e}"))),
+    }
+}
+
+fn run_cipher<C: Cipher>(cipher: &C, action: &str, args: &Args) -> CliResult<()> {
+    let key = match args.get("--key") {
+        Some(key) => Some(cipher.parse_key(key).map_err(CliError::Usage)?),
+        None => None,
+    };
+    let input = read_input(args)?;
+    let output = match action {
+        "encrypt" => {
+            let key = match key {
+                Some(key) => key,
+                None => {
+                    let settings = Settings::from_args(args)?;
+                    let key = cipher.generate_key(&mut settings.rng());
+                    eprintln!("Key: {}", cipher.format_key(&key));
+                    key
+                },
+            };
+            cipher.encrypt_text(&input, &key).map_err(CliError::Failed)?
+        },
+        "decrypt" => {
+            let key = key.ok_or(CliError::Usage("decrypt needs --key".to_string()))?;
+            cipher.decrypt_text(&input, &key).map_err(CliError::Failed)?
+        },
+        "crack" => {
+            // Crackers only see the letters, the rest of the text is put
+            // back after.
+            let letters = crack(cipher.name(), &input, args)?;
+            return write_output(args, &render(args, &Layout::new(&input), &letters, true)?);
+        },
+        _ => return Err(CliError::Usage(format!("Invalid action {action}"))),
+    };
+    let layout = Layout::new(&output);
+    write_output(args, &render(args, &layout, layout.letters(), false)?)
+}
+
+/// Puts `letters` back into `layout`, segmenting them into words if they
+/// were `cracked` and `--segment` is given.
+fn render(args: &Args, layout: &Layout, letters: &str, cracked: bool) -> CliResult<String> {
+    let mut output = if args.flag("--letters-only") {
+        layout.render_letters(letters)
+    } else {
+        layout.render(letters)
+    };
+    if cracked && args.flag("--segment") {
+        output = segment::segment_text(&output, &load_word_model(args)?);
+    }
+    Ok(output)
+}
+
+fn parse_order(args: &Args) -> CliResult<usize> {
+    let order = args.parse_number("--order", fitness::DEFAULT_ORDER)?;
+    if !fitness::ORDERS.contains(&order) {
+        return Err(CliError::Usage(format!("--order must be in range {:?}", fitness::ORDERS)));
+    }
+    Ok(order)
+}
+
+fn parse_precision(args: &Args, default: Precision) -> CliResult<Precision> {
+    match args.get("--precision") {
+        None => Ok(default),
+        Some("full") => Ok(Precision::Full),
+        Some(bits) => match bits.parse::<u8>() {
+            Ok(bits @ (1 | 2 | 4 | 8 | 16)) => Ok(Precision::Quantised(bits)),
+            _ => Err(CliError::Usage(format!("Invalid value for --precision: {bits}"))),
+        },
+    }
+}
+
+/// Loads the model given by `--model`, or the one of `--lang` and `--order`,
+/// at the precision given by `--precision`.
+fn load_model(args: &Args) -> CliResult<FitnessMatrix> {
+    match args.get("--model") {
+        Some(path) => {
+            let matrix = fitness::load_fitness_matrix(path).map_err(CliError::Failed)?;
+            if args.get("--order").is_some() && matrix.order() != parse_order(args)? {
+                return Err(CliError::Failed(
+                    format!("{path} has n-gram order {}", matrix.order())));
+            }
+            // Models already quantised are used as they are.
+            let default = match matrix.precision() {
+                Precision::Full => Precision::FAST,
+                precision => precision,
+            };
+            let precision = parse_precision(args, default)?;
+            matrix.with_precision(precision).map_err(CliError::Failed)
+        },
+        None => language_model(args, parse_order(args)?),
+    }
+}
+
+/// Loads the model of `--lang` and `order`, at the precision given by
+/// `--precision`.
+fn language_model(args: &Args, order: usize) -> CliResult<FitnessMatrix> {
+    let precision = parse_precision(args, Precision::FAST)?;
+    let language = args.get("--lang").unwrap_or(fitness::DEFAULT_LANGUAGE);
+    let model_dir = args.get("--model-dir").unwrap_or("models");
+    fitness::language_fitness_matrix(language, order, precision, model_dir)
+        .map_err(CliError::Failed)
+}
+
+/// Loads the word model given by `--words`, or the one of `--lang`.
+fn load_word_model(args: &Args) -> CliResult<WordModel> {
+    match args.get("--words") {
+        Some(path) => segment::load_word_model(path),
+        None => {
+            let language = args.get("--lang").unwrap_or(fitness::DEFAULT_LANGUAGE);
+            let model_dir = args.get("--model-dir").unwrap_or("models");
+            segment::language_word_model(language, model_dir)
+        },
+    }.map_err(CliError::Failed)
+}
+
+/// Cracks `ciphertext`, reports the key on stderr and returns the letters of
+/// the plaintext.
+fn crack(cipher: &str, ciphertext: &str, args: &Args) -> CliResult<String> {
+    let ciphertext = &Normaliser::ciphertext(false).normalise(ciphertext);
+    let settings = Settings::from_args(args)?;
+    let matrix = load_model(args)?;
+    let (key, plaintext, fitness, strategy) = match cipher {
+        "caesar" => {
+            let cracks = caesar::crack(ciphertext, matrix.unigram()).map_err(CliError::Failed)?;
+            let (chi, key) = cracks[0];
+            let plaintext = caesar::decrypt(ciphertext, key).map_err(CliError::Failed)?;
+            eprintln!("Key: {key}");
+            eprintln!("Chi squared: {chi}");
+            return Ok(plaintext);
+        },
+        "vigenere" | "beaufort" | "variant-beaufort" | "gronsfeld" => {
+            let method = args.get("--method").unwrap_or("search");
+            let options = settings.options(vigenere::search_options());
+            let crack_vigenere = |ciphertext: &str| match method {
+                "columns" => vigenere::crack_columns(ciphertext, &matrix, false, true),
+                "polish" => vigenere::crack_columns(ciphertext, &matrix, true, true),
+                _ => vigenere::crack(ciphertext, &matrix, &options),
+            };
+            if !["search", "columns", "polish"].contains(&method) {
+                return Err(CliError::Usage(
+                    format!("Invalid method {method} (use search, columns or polish)")));
+            }
+            let (result, plaintext) = if cipher == "vigenere" {
+                let result = crack_vigenere(ciphertext)
+                    .map_err(|e| CliError::Failed(e.to_string()))?;
+                let plaintext = vigenere::decrypt(ciphertext, &result.key);
+                (result, plaintext)
+            } else {
+                // The variants look alike, so all are tried whichever was asked.
+                let (variant, result) = vigenere::crack_variants(ciphertext, crack_vigenere)
+                    .map_err(|e| CliError::Failed(e.to_string()))?;
+                eprintln!("Variant: {}", variant.name());
+                let plaintext = match variant {
+                    Variant::Beaufort => vigenere::beaufort_decrypt(ciphertext, &result.key),
+                    Variant::VariantBeaufort =>
+                        vigenere::variant_beaufort_decrypt(ciphertext, &result.key),
+                    Variant::Gronsfeld => vigenere::gronsfeld_decrypt(ciphertext, &result.key),
+                };
+                (result, plaintext)
+            };
+            let plaintext = plaintext.map_err(CliError::Failed)?;
+            // Only the random restarts depend on the strategy and seed.
+            let strategy = (method == "search").then_some(result.strategy);
+            (result.key, plaintext, result.fitness, strategy)
+        },
+        "autokey" => {
+            let options = settings.options(autokey::search_options());
+            let result = autokey::crack(ciphertext, &matrix, &options)
+                .map_err(|e| CliError::Failed(e.to_string()))?;
+            let plaintext = autokey::decrypt(ciphertext, &result.key)
+                .map_err(CliError::Failed)?;
+            (result.key, plaintext, result.fitness, Some(result.strategy))
+        },
+        "running-key" => {
+            let (fitness, plaintext, key) =
+                running_key::crack(ciphertext, &matrix, running_key::BEAM_WIDTH)
+                    .map_err(|e| CliError::Failed(e.to_string()))?;
+            (key, plaintext, fitness, None)
+        },
+        "substitution" => {
+            let options = settings.options(substitution::search_options());
+            let result = substitution::crack(ciphertext, &matrix, &options)
+                .map_err(|e| CliError::Failed(e.to_string()))?;
+            let plaintext = substitution::decrypt(ciphertext, &result.key)
+                .map_err(CliError::Failed)?;
+            let key = Substitution.format_key(&reverse_permutation(&result.key));
+            (key, plaintext, result.fitness, Some(result.strategy))
+        },
+        "enigma" => {
+            if args.get("--machine").is_some_and(|machine| machine != "m3") {
+                return Err(CliError::Usage(
+                    "Only the three-wheel enigma can be cracked".to_string()));
+            }
+            let (fitness, key) = enigma::crack(ciphertext, &language_model(args, 2)?,
+                &language_model(args, 3)?, &enigma_options(args)?)
+                .map_err(|e| CliError::Failed(e.to_string()))?;
+            let plaintext = EnigmaM3.decrypt(ciphertext, &key).map_err(CliError::Failed)?;
+            (key.format(), plaintext, fitness, None)
+        },
+        _ => return Err(CliError::Usage(format!("Invalid cipher {cipher}"))),
+    };
+    eprintln!("Key: {key}");
+    eprintln!("Fitness: {fitness}");
+    if let Some(strategy) = strategy {
+        eprintln!("Strategy: {}", strategy.name());
+        eprintln!("Seed: {}", settings.seed);
+    }
+    Ok(plaintext.replace(' ', ""))
+}
+
+/// Runs the bombe on `menu`, verifies its stops and returns the plaintext
+/// of the best, reporting the menu and key on stderr.
+fn run_bombe(ciphertext: &str, menu: &bombe::Menu, args: &Args) -> CliResult<String> {
+    let loops = menu.loops();
+    let letter = |x: u8| (x + b'A') as char;
+    eprintln!("Menu: {} letters, {} links, {} loops, test letter {}", menu.letters().len(),
+        menu.pairs.len(), loops.len(), letter(menu.test_letter()));
+    for positions in &loops {
+        let links: Vec<String> = positions.iter()
+            .map(|idx| {
+                let (p, c) = menu.pairs[*idx];
+                format!("{}{}", letter(p), letter(c))
+            })
+            .collect();
+        eprintln!("Loop: {}", links.join(" "));
+    }
+    let options = enigma_options(args)?;
+    let stops = bombe::run(menu, &options);
+    eprintln!("Stops: {}", stops.len());
+    let (fitness, key) = bombe::solve(ciphertext, menu, &stops, &language_model(args, 2)?,
+        &language_model(args, 3)?, &options)
+        .map_err(|e| CliError::Failed(e.to_string()))?;
+    eprintln!("Key: {}", key.format());
+    eprintln!("Fitness: {fitness}");
+    EnigmaM3.decrypt(ciphertext, &key).map_err(CliError::Failed)
+}
+
+/// Settings of the enigma attacks: `--rotors` and `--threads`.
+fn enigma_options(args: &Args) -> CliResult<enigma::CrackOptions> {
+    let rotors = match args.get("--rotors") {
+        Some(rotors) => rotors.split('-')
+            .map(Rotor::parse)
+            .collect::<Result<Vec<Rotor>, String>>()
+            .map_err(CliError::Usage)?,
+        None => enigma::CrackOptions::default().rotors,
+    };
+    Ok(enigma::CrackOptions {
+        rotors,
+        threads: Settings::from_args(args)?.threads,
+        report: true,
+        ..enigma::CrackOptions::default()
+    })
+}
+
+fn run(args: &Args) -> CliResult<()> {
+    let command = args.positional.first()
+        .ok_or(CliError::Usage("Missing command".to_string()))?;
+    let action = || args.positional.get(1)
+        .map(|x| x.as_str())
+        .ok_or(CliError::Usage(format!("Missing action for {command}")));
+    let key_length_or = |default: usize| {
+        let key_length = args.parse_number("--key-length", default)?;
+        if key_length == 0 {
+            return Err(CliError::Usage("--key-length must be at least 1".to_string()));
         }
+        Ok(key_length)
+    };
+    let key_length = || key_length_or(8);
+    match command.as_str() {
+        "help" => {
+            println!("{USAGE}");
+            Ok(())
+        },
+        "caesar" => run_cipher(&Caesar, action()?, args),
+        "vigenere" => run_cipher(&Vigenere { key_length: key_length()? }, action()?, args),
+        "beaufort" => run_cipher(&Beaufort { key_length: key_length()? }, action()?, args),
+        "variant-beaufort" =>
+            run_cipher(&VariantBeaufort { key_length: key_length()? }, action()?, args),
+        "gronsfeld" => run_cipher(&Gronsfeld { key_length: key_length()? }, action()?, args),
+        "autokey" => run_cipher(&Autokey { key_length: key_length()? }, action()?, args),
+        "running-key" =>
+            run_cipher(&RunningKey { key_length: key_length_or(1000)? }, action()?, args),
+        "substitution" => run_cipher(&Substitution, action()?, args),
+        "enigma" => match args.get("--machine").unwrap_or("m3") {
+            "m3" => run_cipher(&EnigmaM3, action()?, args),
+            "m4" => run_cipher(&EnigmaM4, action()?, args),
+            machine => Err(CliError::Usage(format!("Invalid machine {machine} (use m3 or m4)"))),
+        },
+        "identify" => {
+            let input = read_input(args)?;
+            let (features, ranked) = identify::identify(&input).map_err(CliError::Failed)?;
+            let mut report = format!("Letters: {}\n", features.length);
+            report += &format!("Alphabet size: {}\n", features.alphabet_size);
+            report += &format!("IC: {:.4}\n", features.ic);
+            report += &format!("Chi squared per letter: {:.3}\n", features.chi_squared);
+            report += &format!("Shifted chi squared per letter: {:.3}\n",
+                features.shifted_chi_squared);
+            report += &format!("Period: {} (IC {:.4})\n", features.period, features.periodic_ic);
+            let key_lengths: Vec<String> = vigenere::rank_key_lengths(&Normaliser::ciphertext(false).normalise(&input))
+                .iter()
+                .take(3)
+                .map(|(length, score)| format!("{length} ({score:.2})"))
+                .collect();
+            report += &format!("Key lengths: {}\n", key_lengths.join(", "));
+            report += &format!("Doubled letters: {:.3} ({} in pairs)\n",
+                features.doubled, features.doubled_in_pairs);
+            report += &format!("Even length: {}\n", features.even_length);
+            for (family, confidence) in ranked {
+                report += &format!("\n{:>5.1}% {}", confidence * 100.0, family.name());
+            }
+            write_output(args, &report)
+        },
+        "auto" => {
+            let input = read_input(args)?;
+            let settings = Settings::from_args(args)?;
+            let matrix = load_model(args)?;
+            let budget: f64 = args.parse_number("--budget", 60.0)?;
+            if !(budget > 0.0 && budget.is_finite()) {
+                return Err(CliError::Usage("--budget must be a positive number".to_string()));
+            }
+            let solutions = auto::solve(&input, &matrix, &settings.options(SearchOptions::default()),
+                Duration::from_secs_f64(budget)).map_err(CliError::Failed)?;
+            let best = solutions.first()
+                .ok_or(CliError::Failed("No cipher could be cracked".to_string()))?;
+            eprintln!("Cipher: {}", best.family.name());
+            eprintln!("Key: {}", best.key);
+            eprintln!("Fitness: {}", best.fitness);
+            eprintln!("Seed: {}", settings.seed);
+            write_output(args, &render(args, &Layout::new(&input), &best.plaintext, true)?)
+        },
+        "bombe" => {
+            let input = read_input(args)?;
+            let ciphertext = Normaliser::ciphertext(false).normalise(&input);
+            let crib = args.get("--crib")
+                .ok_or(CliError::Usage("bombe needs --crib".to_string()))?;
+            let crib = Normaliser::ciphertext(false).normalise(crib);
+            let offset = args.parse_number("--offset", 0)?;
+            let menu = bombe::Menu::new(&ciphertext, &crib, offset).map_err(CliError::Failed)?;
+            let plaintext = run_bombe(&ciphertext, &menu, args)?;
+            write_output(args, &render(args, &Layout::new(&input), &plaintext, true)?)
+        },
+        "crib" => {
+            let input = read_input(args)?;
+            let ciphertext = Normaliser::ciphertext(false).normalise(&input);
+            let crib = args.get("--crib")
+                .ok_or(CliError::Usage("crib needs --crib".to_string()))?;
+            let crib = Normaliser::ciphertext(false).normalise(crib);
+            let max_positions = args.parse_number("--max-positions", usize::MAX)?;
+            if max_positions == 0 {
+                return Err(CliError::Usage("--max-positions must be at least 1".to_string()));
+            }
+            let positions = bombe::crib_positions(&ciphertext, &crib);
+            let positions: Vec<String> = positions.iter().map(|x| x.to_string()).collect();
+            if args.flag("--positions-only") {
+                return write_output(args, &positions.join(" "));
+            }
+            eprintln!("Crib positions: {}", positions.join(" "));
+            let (fitness, key, menu) = bombe::crack(&ciphertext, &crib, max_positions,
+                &language_model(args, 2)?, &language_model(args, 3)?, &enigma_options(args)?)
+                .map_err(|e| CliError::Failed(e.to_string()))?;
+            eprintln!("Offset: {}", menu.offset);
+            eprintln!("Key: {}", key.format());
+            eprintln!("Fitness: {fitness}");
+            let plaintext = EnigmaM3.decrypt(&ciphertext, &key).map_err(CliError::Failed)?;
+            write_output(args, &render(args, &Layout::new(&input), &plaintext, true)?)
+        },
+        "build-model" => {
+            let corpus = args.get("--corpus")
+                .ok_or(CliError::Usage("build-model needs --corpus".to_string()))?;
+            let language = args.get("--lang").unwrap_or(fitness::DEFAULT_LANGUAGE);
+            let order = parse_order(args)?;
+            let path = match args.get("--output") {
+                Some(path) => path.to_string(),
+                None => {
+                    let model_dir = args.get("--model-dir").unwrap_or("models");
+                    fs::create_dir_all(model_dir)
+                        .map_err(|e| CliError::Failed(format!("Cannot create {model_dir}: {e}")))?;
+                    Path::new(model_dir).join(fitness::model_file_name(language, order))
+                        .to_string_lossy()
+                        .into_owned()
+                },
+            };
+            let precision = parse_precision(args, Precision::Full)?;
+            let mut normaliser = Normaliser::default();
+            if let Some(policy) = args.get("--digits") {
+                normaliser.digits = CharPolicy::parse(policy).map_err(CliError::Usage)?;
+            }
+            if let Some(policy) = args.get("--punctuation") {
+                normaliser.punctuation = CharPolicy::parse(policy).map_err(CliError::Usage)?;
+            }
+            normaliser.spaces = !args.flag("--letters-only");
+            fitness::make_fitness_matrix_file(corpus, &path, order, language, precision,
+                &normaliser).map_err(CliError::Failed)?;
+            eprintln!("Model written to {path}");
+            let words_path = match args.get("--words") {
+                Some(words_path) => words_path.to_string(),
+                None => Path::new(&path).with_file_name(segment::word_model_file_name(language))
+                    .to_string_lossy()
+                    .into_owned(),
+            };
+            segment::make_word_model_file(corpus, args.get("--dictionary"), &words_path,
+                &normaliser)
+                .map_err(CliError::Failed)?;
+            eprintln!("Word model written to {words_path}");
+            Ok(())
+        },
+        _ => Err(CliError::Usage(format!("Unknown command {command}"))),
+    }
+}
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match Args::parse(&args).and_then(|args| run(&args)) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(CliError::Usage(msg)) => {
+            eprintln!("error: {msg}");
+            eprintln!("{USAGE}");
+            ExitCode::from(2)
+        },
+        Err(CliError::Failed(msg)) => {
+            eprintln!("error: {msg}");
+            ExitCode::FAILURE
+        },
     }
 }
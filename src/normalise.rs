@@ -0,0 +1,289 @@
+/// Folds a lowercase char to plain `a..=z` letters, e.g. `é` to `e` and `ß`
+/// to `ss`. Returns `None` for chars that are not letters.
+pub fn fold_char(c: char) -> Option<&'static str> {
+    let folded = match c {
+        'a' => "a", 'b' => "b", 'c' => "c", 'd' => "d", 'e' => "e",
+        'f' => "f", 'g' => "g", 'h' => "h", 'i' => "i", 'j' => "j",
+        'k' => "k", 'l' => "l", 'm' => "m", 'n' => "n", 'o' => "o",
+        'p' => "p", 'q' => "q", 'r' => "r", 's' => "s", 't' => "t",
+        'u' => "u", 'v' => "v", 'w' => "w", 'x' => "x", 'y' => "y",
+        'z' => "z",
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' | 'ā' | 'ă' | 'ą' => "a",
+        'æ' => "ae",
+        'ç' | 'ć' | 'ĉ' | 'ċ' | 'č' => "c",
+        'ď' | 'đ' | 'ð' => "d",
+        'è' | 'é' | 'ê' | 'ë' | 'ē' | 'ĕ' | 'ė' | 'ę' | 'ě' => "e",
+        'ĝ' | 'ğ' | 'ġ' | 'ģ' => "g",
+        'ĥ' | 'ħ' => "h",
+        'ì' | 'í' | 'î' | 'ï' | 'ĩ' | 'ī' | 'ĭ' | 'į' | 'ı' => "i",
+        'ĵ' => "j",
+        'ķ' => "k",
+        'ĺ' | 'ļ' | 'ľ' | 'ŀ' | 'ł' => "l",
+        'ñ' | 'ń' | 'ņ' | 'ň' => "n",
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' | 'ø' | 'ō' | 'ŏ' | 'ő' => "o",
+        'œ' => "oe",
+        'ŕ' | 'ŗ' | 'ř' => "r",
+        'ś' | 'ŝ' | 'ş' | 'š' => "s",
+        'ß' => "ss",
+        'ţ' | 'ť' | 'ŧ' => "t",
+        'þ' => "th",
+        'ù' | 'ú' | 'û' | 'ü' | 'ũ' | 'ū' | 'ŭ' | 'ů' | 'ű' | 'ų' => "u",
+        'ŵ' => "w",
+        'ý' | 'ÿ' | 'ŷ' => "y",
+        'ź' | 'ż' | 'ž' => "z",
+        _ => return None,
+    };
+    Some(folded)
+}
+
+/// What to do with a char that is not a letter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CharPolicy {
+    Remove,
+    /// Treat it as a word break.
+    Space,
+    /// Spell digits out as English words, other chars become word breaks.
+    Spell,
+}
+
+impl CharPolicy {
+    pub fn parse(name: &str) -> Result<CharPolicy, String> {
+        match name {
+            "remove" => Ok(CharPolicy::Remove),
+            "space" => Ok(CharPolicy::Space),
+            "spell" => Ok(CharPolicy::Spell),
+            _ => Err(format!("Invalid policy {name} (use remove, space or spell)")),
+        }
+    }
+}
+
+const DIGIT_NAMES: [&str; 10] = [
+    "zero", "one", "two", "three", "four",
+    "five", "six", "seven", "eight", "nine",
+];
+
+/// Turns raw text into the `a..=z` (and optionally space) form that fitness
+/// models and crackers work on: lowercases, folds diacritics, applies the
+/// digit and punctuation policies and collapses whitespace.
+#[derive(Clone, Debug)]
+pub struct Normaliser {
+    pub digits: CharPolicy,
+    pub punctuation: CharPolicy,
+    /// Keep single spaces between words, otherwise drop them.
+    pub spaces: bool,
+    /// Drop the Project Gutenberg header and licence, see [`strip_gutenberg`].
+    pub strip_gutenberg: bool,
+}
+
+impl Default for Normaliser {
+    /// Settings for building models from a corpus.
+    fn default() -> Self {
+        Normaliser {
+            digits: CharPolicy::Space,
+            punctuation: CharPolicy::Space,
+            spaces: true,
+            strip_gutenberg: true,
+        }
+    }
+}
+
+impl Normaliser {
+    /// Settings for ciphertext: letters only, spaces kept if `spaces`.
+    pub fn ciphertext(spaces: bool) -> Normaliser {
+        Normaliser {
+            digits: CharPolicy::Remove,
+            punctuation: CharPolicy::Remove,
+            spaces,
+            strip_gutenberg: false,
+        }
+    }
+
+    pub fn normalise(&self, text: &str) -> String {
+        let text = if self.strip_gutenberg { strip_gutenberg(text) } else { text };
+        let mut normalised = String::with_capacity(text.len());
+        // Whether a word break is waiting for the next letter.
+        let mut word_break = false;
+        let push_word = |normalised: &mut String, word: &str, word_break: bool| {
+            if word_break && self.spaces && !normalised.is_empty() {
+                normalised.push(' ');
+            }
+            normalised.push_str(word);
+        };
+        for c in text.chars().flat_map(char::to_lowercase) {
+            if let Some(folded) = fold_char(c) {
+                push_word(&mut normalised, folded, word_break);
+                word_break = false;
+                continue;
+            }
+            let policy = if c.is_whitespace() {
+                CharPolicy::Space
+            } else if c.is_ascii_digit() {
+                self.digits
+            } else if c == '\'' || c == '’' {
+                // Keeps contractions such as "don't" in one word.
+                CharPolicy::Remove
+            } else {
+                self.punctuation
+            };
+            match policy {
+                CharPolicy::Remove => {},
+                CharPolicy::Space => word_break = true,
+                CharPolicy::Spell => {
+                    if let Some(digit) = c.to_digit(10) {
+                        push_word(&mut normalised, DIGIT_NAMES[digit as usize], true);
+                    }
+                    word_break = true;
+                },
+            }
+        }
+        normalised
+    }
+}
+
+/// Returns the text between the `*** START OF` and `*** END OF` markers of
+/// a Project Gutenberg book, or all of `text` when they are missing.
+pub fn strip_gutenberg(text: &str) -> &str {
+    let start = text.find("*** START OF")
+        .and_then(|pos| text[pos..].find('\n').map(|end| pos + end + 1))
+        .unwrap_or(0);
+    let end = text[start..].find("*** END OF")
+        .map(|pos| start + pos)
+        .unwrap_or(text.len());
+    &text[start..end]
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Slot {
+    Lower,
+    Upper,
+    Other(char),
+}
+
+/// The case and non-letter chars of a text, so ciphers can work on its
+/// letters alone and the result can be put back in the original layout.
+/// Letters are folded like [`Normaliser`] does, so `É` becomes an upper `e`.
+#[derive(Clone, Debug)]
+pub struct Layout {
+    slots: Vec<Slot>,
+    letters: String,
+}
+
+impl Layout {
+    pub fn new(text: &str) -> Layout {
+        let mut slots = Vec::with_capacity(text.len());
+        let mut letters = String::with_capacity(text.len());
+        for c in text.chars() {
+            let folded: Vec<&str> = c.to_lowercase().filter_map(fold_char).collect();
+            if folded.is_empty() {
+                slots.push(Slot::Other(c));
+                continue;
+            }
+            let slot = if c.is_uppercase() { Slot::Upper } else { Slot::Lower };
+            for letter in folded.into_iter().flat_map(str::chars) {
+                slots.push(slot);
+                letters.push(letter);
+            }
+        }
+        Layout { slots, letters }
+    }
+
+    /// The lowercase `a..=z` letters of the text.
+    pub fn letters(&self) -> &str {
+        &self.letters
+    }
+
+    /// Puts `letters` back in the place of the original letters, restoring
+    /// their case and keeping every other char.
+    pub fn render(&self, letters: &str) -> String {
+        self.render_with(letters, true)
+    }
+
+    /// Like [`Layout::render`], but drops the chars that are not letters.
+    pub fn render_letters(&self, letters: &str) -> String {
+        self.render_with(letters, false)
+    }
+
+    fn render_with(&self, letters: &str, keep_other: bool) -> String {
+        let mut letters = letters.chars();
+        let mut rendered = String::with_capacity(self.slots.len());
+        for slot in &self.slots {
+            match slot {
+                Slot::Other(c) => if keep_other {
+                    rendered.push(*c);
+                },
+                Slot::Lower => rendered.extend(letters.next()),
+                Slot::Upper => rendered.extend(letters.next().map(|x| x.to_ascii_uppercase())),
+            }
+        }
+        // Letters beyond the original ones are kept rather than lost.
+        rendered.extend(letters);
+        rendered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_restores_case_spacing_and_punctuation() {
+        let layout = Layout::new("  Hello,  World!\n");
+        assert_eq!(layout.letters(), "helloworld");
+        assert_eq!(layout.render("ifmmpxpsme"), "  Ifmmp,  Xpsme!\n");
+        assert_eq!(layout.render_letters("ifmmpxpsme"), "IfmmpXpsme");
+    }
+
+    #[test]
+    fn render_folds_diacritics_and_keeps_extra_letters() {
+        let layout = Layout::new("Été");
+        assert_eq!(layout.letters(), "ete");
+        assert_eq!(layout.render("abc"), "Abc");
+        assert_eq!(layout.render("abcd"), "Abcd");
+    }
+
+    #[test]
+    fn normalise_folds_diacritics_and_sharp_s() {
+        let normaliser = Normaliser::default();
+        assert_eq!(normaliser.normalise("Straße Æsop Café"), "strasse aesop cafe");
+        assert_eq!(fold_char('ß'), Some("ss"));
+        assert_eq!(fold_char('-'), None);
+    }
+
+    #[test]
+    fn normalise_keeps_contractions_in_one_word() {
+        let normaliser = Normaliser::default();
+        assert_eq!(normaliser.normalise("Don't stop, it’s late."), "dont stop its late");
+    }
+
+    #[test]
+    fn normalise_applies_the_digit_policy() {
+        let mut normaliser = Normaliser::default();
+        assert_eq!(normaliser.normalise("b4u 42"), "b u");
+        normaliser.digits = CharPolicy::Remove;
+        assert_eq!(normaliser.normalise("b4u 42"), "bu");
+        normaliser.digits = CharPolicy::Spell;
+        assert_eq!(normaliser.normalise("b4u 42"), "b four u four two");
+        assert_eq!(CharPolicy::parse("spell"), Ok(CharPolicy::Spell));
+        assert!(CharPolicy::parse("keep").is_err());
+    }
+
+    #[test]
+    fn normalise_collapses_whitespace() {
+        let normaliser = Normaliser::default();
+        assert_eq!(normaliser.normalise("  The\t cat --\n\n sat.  "), "the cat sat");
+        assert_eq!(Normaliser::ciphertext(false).normalise("  The\t cat --\n sat. "),
+            "thecatsat");
+        assert_eq!(Normaliser::ciphertext(true).normalise("  The\t cat --\n sat. "),
+            "the cat sat");
+    }
+
+    #[test]
+    fn strip_gutenberg_keeps_the_text_between_the_markers() {
+        let book = "Licence\n*** START OF THIS PROJECT GUTENBERG EBOOK ***\nThe text.\n\
+            *** END OF THIS PROJECT GUTENBERG EBOOK ***\nMore licence\n";
+        assert_eq!(strip_gutenberg(book), "The text.\n");
+        assert_eq!(strip_gutenberg("No markers"), "No markers");
+        assert_eq!(Normaliser::default().normalise(book), "the text");
+        assert!(Normaliser::ciphertext(true).normalise(book).starts_with("licence start of"));
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::BTreeMap;
+
+use rand::Rng;
+
+use crate::cipher::Cipher;
+use crate::fitness::{FitnessMatrix, SYMBOLS, compute_fitness};
+use crate::normalise::Normaliser;
+use crate::vigenere;
+
+/// Running key cipher: Vigenère whose key is a passage of text at least as
+/// long as the plaintext. Generated keys are `key_length` random letters,
+/// which makes it a one-time pad.
+pub struct RunningKey {
+    pub key_length: usize,
+}
+
+/// Hypotheses [`crack`] keeps at every letter.
+pub const BEAM_WIDTH: usize = 2000;
+
+pub fn encrypt(plaintext: &str, key: &str) -> Result<String, String> {
+    vigenere::encrypt(plaintext, &key_prefix(key, plaintext)?)
+}
+
+pub fn decrypt(ciphertext: &str, key: &str) -> Result<String, String> {
+    vigenere::decrypt(ciphertext, &key_prefix(key, ciphertext)?)
+}
+
+/// The first letters of `key`, as many as `text` has chars.
+fn key_prefix(key: &str, text: &str) -> Result<String, String> {
+    let len = text.chars().count();
+    let prefix: String = key.chars().take(len).collect();
+    if prefix.chars().count() < len {
+        return Err(format!("Running key must have at least {len} letters"));
+    }
+    Ok(prefix)
+}
+
+/// A partial decryption: the last letters of its plaintext and key as
+/// n-gram indices, and how it was reached.
+#[derive(Clone, Copy)]
+struct Hypothesis {
+    score: f64,
+    plain: usize,
+    key: usize,
+    parent: usize,
+    letter: u8,
+}
+
+/// Beam search over plaintext letters scoring both the plaintext and the
+/// key it implies, `ciphertext - plaintext`, as text of the model's
+/// language. Keeps the `beam_width` best hypotheses at every letter, and
+/// returns `(fitness, plaintext, key)`.
+///
+/// Plaintext and key play the same part, so the two may come out swapped.
+pub fn crack(ciphertext: &str, matrix: &FitnessMatrix, beam_width: usize)
+    -> Result<(f64, String, String), &'static str>
+{
+    let order = matrix.order();
+    if ciphertext.len() < order * 4 {
+        return Err("Cipher is too short for the n-gram order");
+    }
+    if !ciphertext.chars().all(|x| x.is_ascii_lowercase()) {
+        return Err("Cipher must only contain a..=z");
+    }
+    if beam_width == 0 {
+        return Err("Beam width must be at least 1");
+    }
+    let symbols = SYMBOLS.len();
+    // Indices of the last `order - 1` letters, the state that matters for
+    // the next n-gram.
+    let state_size = symbols.pow(order as u32 - 1);
+    let ciphertext: Vec<u8> = ciphertext.bytes().map(|x| x - b'a').collect();
+
+    let mut layers: Vec<Vec<Hypothesis>> = Vec::with_capacity(ciphertext.len());
+    let mut beam = vec![Hypothesis { score: 0.0, plain: 0, key: 0, parent: 0, letter: 0 }];
+    for (pos, c) in ciphertext.iter().enumerate() {
+        // Ordered by state, so hypotheses that tie keep the same order on
+        // every run and the beam is cut in the same place.
+        let mut best: BTreeMap<usize, Hypothesis> = BTreeMap::new();
+        for (parent, hypothesis) in beam.iter().enumerate() {
+            for letter in 0..26u8 {
+                let key_letter = (c + 26 - letter) % 26;
+                let plain = hypothesis.plain * symbols + letter as usize;
+                let key = hypothesis.key * symbols + key_letter as usize;
+                let mut score = hypothesis.score;
+                if pos + 1 >= order {
+                    score += matrix.ngram_fitness(plain) + matrix.ngram_fitness(key);
+                }
+                let next = Hypothesis {
+                    score,
+                    plain: plain % state_size,
+                    key: key % state_size,
+                    parent,
+                    letter,
+                };
+                // The key letters follow from the plaintext ones, so the
+                // plaintext state alone tells hypotheses apart.
+                best.entry(next.plain)
+                    .and_modify(|current| if next.score > current.score { *current = next })
+                    .or_insert(next);
+            }
+        }
+        let mut next_beam: Vec<Hypothesis> = best.into_values().collect();
+        next_beam.sort_by(|h1, h2| h2.score.partial_cmp(&h1.score).unwrap());
+        // Before the first full n-gram every hypothesis scores alike.
+        if pos + 1 >= order {
+            next_beam.truncate(beam_width);
+        }
+        layers.push(std::mem::replace(&mut beam, next_beam));
+    }
+    layers.push(beam);
+
+    let mut letters = Vec::with_capacity(ciphertext.len());
+    let mut idx = 0;
+    for layer in layers[1..].iter().rev() {
+        letters.push(layer[idx].letter);
+        idx = layer[idx].parent;
+    }
+    letters.reverse();
+    let plaintext: String = letters.iter().map(|x| (x + b'a') as char).collect();
+    let key: String = ciphertext.iter().zip(&letters)
+        .map(|(c, p)| ((c + 26 - p) % 26 + b'a') as char)
+        .collect();
+    let fitness = (compute_fitness(&letters, matrix)
+        + compute_fitness(&key.bytes().map(|x| x - b'a').collect::<Vec<u8>>(), matrix)) / 2.0;
+    Ok((fitness, plaintext, key))
+}
+
+impl Cipher for RunningKey {
+    type Key = String;
+
+    fn name(&self) -> &'static str {
+        "running-key"
+    }
+
+    fn encrypt(&self, plaintext: &str, key: &String) -> Result<String, String> {
+        encrypt(plaintext, key)
+    }
+
+    fn decrypt(&self, ciphertext: &str, key: &String) -> Result<String, String> {
+        decrypt(ciphertext, key)
+    }
+
+    /// Any text, of which only the letters are used.
+    fn parse_key(&self, key: &str) -> Result<String, String> {
+        let key = Normaliser::ciphertext(false).normalise(key);
+        if key.is_empty() {
+            return Err("Running key must have letters".to_string());
+        }
+        Ok(key)
+    }
+
+    fn format_key(&self, key: &String) -> String {
+        key.clone()
+    }
+
+    fn generate_key<R: Rng + ?Sized>(&self, rng: &mut R) -> String {
+        vigenere::generate_key(self.key_length, rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fitness::Precision;
+
+    #[test]
+    fn non_ascii_keys_are_rejected() {
+        assert!(encrypt("abc", "éé").is_err());
+        assert!(encrypt("abc", "éééé").is_err());
+        assert!(decrypt("abc", "éé").is_err());
+        assert!(encrypt("abc", "ab").is_err());
+        assert_eq!(decrypt(&encrypt("abc", "keyx").unwrap(), "keyx").unwrap(), "abc");
+    }
+
+    #[test]
+    fn crack_is_deterministic() {
+        // A coarse model makes many hypotheses tie.
+        let matrix = FitnessMatrix::from_corpus("the cat sat on the mat and the dog sat on the log",
+            3, "en").unwrap()
+            .with_precision(Precision::Quantised(1))
+            .unwrap();
+        let ciphertext = encrypt("thecatsatonthemat", "andthedogsatonthe").unwrap();
+        let first = crack(&ciphertext, &matrix, 50).unwrap();
+        for _ in 0..5 {
+            assert_eq!(crack(&ciphertext, &matrix, 50).unwrap(), first);
+        }
+    }
+}
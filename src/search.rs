@@ -0,0 +1,267 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Instant;
+
+use rand::rngs::StdRng;
+use rand::{thread_rng, Rng, SeedableRng};
+
+/// The key space a cracker searches, described by its neighbourhood moves.
+pub trait Neighbourhood {
+    type Key: Clone;
+
+    fn random_key<R: Rng + ?Sized>(&self, rng: &mut R) -> Self::Key;
+
+    /// Number of neighbours every key has.
+    fn neighbour_count(&self) -> usize;
+
+    /// Returns the `idx`-th neighbour of `key`, `idx < neighbour_count()`.
+    fn neighbour(&self, key: &Self::Key, idx: usize) -> Self::Key;
+}
+
+/// Scores a candidate key, higher is better.
+pub trait Scorer<K> {
+    fn score(&self, key: &K) -> f64;
+}
+
+/// Anything that can be searched: a neighbourhood plus a scorer for its keys.
+pub trait Cracker: Neighbourhood + Scorer<<Self as Neighbourhood>::Key> {}
+
+impl<T> Cracker for T
+    where T: Neighbourhood + Scorer<<T as Neighbourhood>::Key> {}
+
+/// How the temperature falls from `start_temperature` to `end_temperature`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Cooling {
+    Linear,
+    Geometric,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Schedule {
+    pub start_temperature: f64,
+    pub end_temperature: f64,
+    pub steps: usize,
+    pub cooling: Cooling,
+}
+
+impl Schedule {
+    pub fn temperature(&self, step: usize) -> f64 {
+        let progress = step as f64 / self.steps.max(1) as f64;
+        match self.cooling {
+            Cooling::Linear => self.start_temperature
+                + (self.end_temperature - self.start_temperature) * progress,
+            Cooling::Geometric => self.start_temperature
+                * (self.end_temperature / self.start_temperature).powf(progress),
+        }
+    }
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Schedule {
+            start_temperature: 2.0,
+            end_temperature: 0.01,
+            steps: 20000,
+            cooling: Cooling::Geometric,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Strategy {
+    HillClimb,
+    Annealing(Schedule),
+}
+
+impl Strategy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Strategy::HillClimb => "hill climb",
+            Strategy::Annealing(_) => "simulated annealing",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SearchOptions {
+    /// Maximum number of random restarts.
+    pub max_restarts: usize,
+    /// Stop once the best fitness has been reached again this many times.
+    pub max_hits: usize,
+    /// Print progress to stderr every this many restarts, 0 to stay quiet.
+    pub report_every: usize,
+    /// Strategies used by successive restarts, in turn.
+    pub strategies: Vec<Strategy>,
+    /// Number of worker threads running restarts.
+    pub threads: usize,
+    /// Seed of the random keys and annealing moves of every restart.
+    pub seed: u64,
+    /// No restarts are started after this time, though the first always
+    /// runs. Results then depend on timing.
+    pub deadline: Option<Instant>,
+}
+
+impl Default for SearchOptions {
+    fn default() -> Self {
+        SearchOptions {
+            max_restarts: 10000,
+            max_hits: 3,
+            report_every: 0,
+            strategies: vec![Strategy::HillClimb],
+            threads: 1,
+            seed: thread_rng().gen(),
+            deadline: None,
+        }
+    }
+}
+
+pub struct SearchResult<K> {
+    pub fitness: f64,
+    pub key: K,
+    pub restarts: usize,
+    /// The strategy of the restart that found `key`.
+    pub strategy: Strategy,
+}
+
+/// First-improvement hill climb from `key` until no neighbour is better.
+pub fn hill_climb<C: Cracker>(cracker: &C, key: C::Key) -> (f64, C::Key) {
+    let mut key = key;
+    let mut current = cracker.score(&key);
+    loop {
+        let mut better_key = false;
+        for idx in 0..cracker.neighbour_count() {
+            let proposal_key = cracker.neighbour(&key, idx);
+            let proposal = cracker.score(&proposal_key);
+            if proposal > current {
+                key = proposal_key;
+                current = proposal;
+                better_key = true;
+            }
+        }
+        if !better_key { break; }
+    }
+    (current, key)
+}
+
+/// Simulated annealing from `key`: random neighbours are accepted when
+/// better, or with probability `exp(delta / temperature)` when worse. The
+/// best key seen is finished off with a hill climb.
+pub fn anneal<C, R>(cracker: &C, key: C::Key, schedule: &Schedule, rng: &mut R)
+    -> (f64, C::Key)
+    where C: Cracker, R: Rng + ?Sized
+{
+    let mut key = key;
+    let mut current = cracker.score(&key);
+    let mut best_key = key.clone();
+    let mut best = current;
+    for step in 0..schedule.steps {
+        let temperature = schedule.temperature(step);
+        let idx = rng.gen_range(0..cracker.neighbour_count());
+        let proposal_key = cracker.neighbour(&key, idx);
+        let proposal = cracker.score(&proposal_key);
+        let delta = proposal - current;
+        if delta > 0.0 || rng.gen::<f64>() < (delta / temperature).exp() {
+            key = proposal_key;
+            current = proposal;
+            if current > best {
+                best_key = key.clone();
+                best = current;
+            }
+        }
+    }
+    hill_climb(cracker, best_key)
+}
+
+/// Random generator of restart `restart`, derived from `seed` alone so a
+/// restart behaves the same whichever thread runs it.
+pub fn restart_rng(seed: u64, restart: usize) -> StdRng {
+    StdRng::seed_from_u64(seed ^ (restart as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15))
+}
+
+/// Restart results are merged strictly in restart order, so the best key
+/// and the early stop do not depend on thread timing.
+struct Merge<K> {
+    next: usize,
+    pending: BTreeMap<usize, (f64, K, Strategy)>,
+    fitness: f64,
+    key: K,
+    strategy: Strategy,
+    hits: usize,
+}
+
+/// Searches from random keys with the configured strategies and keeps the
+/// best local maximum. Restarts are shared between `options.threads`
+/// workers; a given seed always gives the same result.
+pub fn search<C>(cracker: &C, options: &SearchOptions) -> SearchResult<C::Key>
+    where C: Cracker + Sync, C::Key: Send
+{
+    let merge = Mutex::new(Merge {
+        next: 1,
+        pending: BTreeMap::new(),
+        fitness: f64::NEG_INFINITY,
+        key: cracker.random_key(&mut restart_rng(options.seed, 0)),
+        strategy: options.strategies[0],
+        hits: 0,
+    });
+    let next_restart = AtomicUsize::new(1);
+    let stop = AtomicBool::new(false);
+
+    let worker = || {
+        while !stop.load(Ordering::Relaxed) {
+            let i = next_restart.fetch_add(1, Ordering::Relaxed);
+            if i > options.max_restarts {
+                break;
+            }
+            if i > 1 && options.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                stop.store(true, Ordering::Relaxed);
+                break;
+            }
+            if options.report_every != 0 && i.is_multiple_of(options.report_every) {
+                eprintln!("{i} processed {:.4}", merge.lock().unwrap().fitness);
+            }
+            let mut rng = restart_rng(options.seed, i);
+            let strategy = options.strategies[(i - 1) % options.strategies.len()];
+            let key = cracker.random_key(&mut rng);
+            let (fitness, key) = match &strategy {
+                Strategy::HillClimb => hill_climb(cracker, key),
+                Strategy::Annealing(schedule) => anneal(cracker, key, schedule, &mut rng),
+            };
+
+            let mut merge = merge.lock().unwrap();
+            merge.pending.insert(i, (fitness, key, strategy));
+            while !stop.load(Ordering::Relaxed) {
+                let next = merge.next;
+                let Some((fitness, key, strategy)) = merge.pending.remove(&next) else {
+                    break;
+                };
+                merge.next += 1;
+                if fitness > merge.fitness {
+                    merge.fitness = fitness;
+                    merge.key = key;
+                    merge.strategy = strategy;
+                } else if fitness == merge.fitness {
+                    merge.hits += 1;
+                    if merge.hits == options.max_hits {
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                }
+            }
+        }
+    };
+    thread::scope(|scope| {
+        for _ in 1..options.threads {
+            scope.spawn(worker);
+        }
+        worker();
+    });
+
+    let merge = merge.into_inner().unwrap();
+    SearchResult {
+        fitness: merge.fitness,
+        key: merge.key,
+        restarts: merge.next - 1,
+        strategy: merge.strategy,
+    }
+}
@@ -0,0 +1,231 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::normalise::Normaliser;
+
+#[cfg(feature = "embedded-model")]
+const EMBEDDED_WORDS: &str = include_str!("../models/en.words");
+
+/// Unigram word frequencies, used to put spaces back into text that has none.
+#[derive(Clone, Debug)]
+pub struct WordModel {
+    counts: HashMap<String, u64>,
+    total: u64,
+    max_len: usize,
+}
+
+impl WordModel {
+    /// Counts the words of a normalised corpus, separated by spaces.
+    pub fn from_corpus(corpus: &str) -> WordModel {
+        let mut counts = HashMap::new();
+        for word in corpus.split(' ').filter(|x| !x.is_empty()) {
+            *counts.entry(word.to_string()).or_insert(0) += 1;
+        }
+        WordModel::from_counts(counts)
+    }
+
+    /// Adds the words of `dictionary`, one per line, that the model lacks
+    /// with a count of one, so that words the corpus happens to miss can
+    /// still be segmented. Lines with anything but letters are skipped.
+    pub fn add_dictionary(&mut self, dictionary: &str) {
+        let mut counts = std::mem::take(&mut self.counts);
+        for word in dictionary.lines().map(|x| x.trim().to_ascii_lowercase()) {
+            if !word.is_empty() && word.chars().all(|x| x.is_ascii_lowercase()) {
+                counts.entry(word).or_insert(1);
+            }
+        }
+        *self = WordModel::from_counts(counts);
+    }
+
+    fn from_counts(counts: HashMap<String, u64>) -> WordModel {
+        let total = counts.values().sum();
+        let max_len = counts.keys().map(|x| x.len()).max().unwrap_or(0);
+        WordModel { counts, total, max_len }
+    }
+
+    /// Reads a model written by [`WordModel::to_text`].
+    pub fn from_text(text: &str) -> Result<WordModel, String> {
+        let mut counts = HashMap::new();
+        for (line_idx, line) in text.lines().enumerate() {
+            let parsed = line.split_once(' ')
+                .filter(|(word, _)| !word.is_empty() && word.chars().all(|x| x.is_ascii_lowercase()))
+                .and_then(|(word, count)| count.parse::<u64>().ok().map(|count| (word, count)));
+            let (word, count) = parsed
+                .ok_or(format!("Invalid word model line {}: '{line}'", line_idx + 1))?;
+            counts.insert(word.to_string(), count);
+        }
+        if counts.is_empty() {
+            return Err("Word model has no words".to_string());
+        }
+        Ok(WordModel::from_counts(counts))
+    }
+
+    /// One `word count` line per word, most frequent first.
+    pub fn to_text(&self) -> String {
+        let mut words: Vec<(&String, &u64)> = self.counts.iter().collect();
+        words.sort_by(|(w1, c1), (w2, c2)| c2.cmp(c1).then(w1.cmp(w2)));
+        words.into_iter()
+            .map(|(word, count)| format!("{word} {count}\n"))
+            .collect()
+    }
+
+    /// Log10 probability of `word`. An unknown word scores as a word seen
+    /// ten times whose letters are drawn at random, so runs of letters lose
+    /// to known words.
+    fn score(&self, word: &str) -> f64 {
+        let total = self.total.max(1) as f64;
+        match self.counts.get(word) {
+            Some(count) => (*count as f64 / total).log10(),
+            None => 1.0 - total.log10() - word.len() as f64 * 26f64.log10(),
+        }
+    }
+}
+
+/// Splits `letters` (`a..=z` without spaces) into the most likely sequence of
+/// words by Viterbi search, and joins them with single spaces.
+pub fn segment(letters: &str, model: &WordModel) -> String {
+    let letters = letters.as_bytes();
+    // best[i]: score of the best split of the first i letters, and where its
+    // last word starts.
+    let mut best: Vec<(f64, usize)> = vec![(0.0, 0); letters.len() + 1];
+    for end in 1..=letters.len() {
+        best[end] = (end.saturating_sub(model.max_len.max(1))..end)
+            .map(|start| {
+                let word = std::str::from_utf8(&letters[start..end]).unwrap();
+                (best[start].0 + model.score(word), start)
+            })
+            .max_by(|(s1, _), (s2, _)| s1.partial_cmp(s2).unwrap())
+            .unwrap();
+    }
+    let mut words = Vec::new();
+    let mut end = letters.len();
+    while end > 0 {
+        let start = best[end].1;
+        words.push(std::str::from_utf8(&letters[start..end]).unwrap());
+        end = start;
+    }
+    words.reverse();
+    words.join(" ")
+}
+
+/// Segments every run of ASCII letters in `text`, keeping their case and
+/// every other char.
+pub fn segment_text(text: &str, model: &WordModel) -> String {
+    let mut segmented = String::with_capacity(text.len() * 6 / 5);
+    let mut run = String::new();
+    let flush = |segmented: &mut String, run: &mut String| {
+        if run.is_empty() {
+            return;
+        }
+        let mut original = run.chars();
+        for c in segment(&run.to_ascii_lowercase(), model).chars() {
+            if c == ' ' {
+                segmented.push(' ');
+            } else {
+                segmented.extend(original.next());
+            }
+        }
+        run.clear();
+    };
+    for c in text.chars() {
+        if c.is_ascii_alphabetic() {
+            run.push(c);
+        } else {
+            flush(&mut segmented, &mut run);
+            segmented.push(c);
+        }
+    }
+    flush(&mut segmented, &mut run);
+    segmented
+}
+
+/// Builds a word model from the UTF-8 corpus at `corpus_path`, after passing
+/// it through `normaliser`, adds the words of `dictionary_path` if given, and
+/// writes it to `path`.
+pub fn make_word_model_file(corpus_path: &str, dictionary_path: Option<&str>, path: &str,
+    normaliser: &Normaliser) -> Result<(), String>
+{
+    let corpus = fs::read_to_string(corpus_path)
+        .map_err(|e| format!("Cannot read {corpus_path}: {e}"))?;
+    let normaliser = Normaliser { spaces: true, ..normaliser.clone() };
+    let mut model = WordModel::from_corpus(&normaliser.normalise(&corpus));
+    if let Some(dictionary_path) = dictionary_path {
+        let dictionary = fs::read_to_string(dictionary_path)
+            .map_err(|e| format!("Cannot read {dictionary_path}: {e}"))?;
+        model.add_dictionary(&dictionary);
+    }
+    fs::write(path, model.to_text())
+        .map_err(|e| format!("Cannot write {path}: {e}"))
+}
+
+pub fn load_word_model(path: &str) -> Result<WordModel, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read {path}: {e}"))?;
+    WordModel::from_text(&text)
+}
+
+/// File name of the word model of `language` inside a model directory.
+pub fn word_model_file_name(language: &str) -> String {
+    format!("{language}.words")
+}
+
+/// The word model of `language`: built in for English, otherwise read from
+/// `model_dir`.
+pub fn language_word_model(language: &str, model_dir: &str) -> Result<WordModel, String> {
+    #[cfg(feature = "embedded-model")]
+    if language == crate::fitness::DEFAULT_LANGUAGE {
+        return WordModel::from_text(EMBEDDED_WORDS);
+    }
+    let path = Path::new(model_dir).join(word_model_file_name(language));
+    load_word_model(&path.to_string_lossy())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model() -> WordModel {
+        WordModel::from_corpus("the age of wisdom it was the age of foolishness it was the \
+            best of times it was the worst of times")
+    }
+
+    #[test]
+    fn segment_splits_known_words() {
+        assert_eq!(segment("itwastheageofwisdom", &model()), "it was the age of wisdom");
+        assert_eq!(segment("theageoffoolishness", &model()), "the age of foolishness");
+        assert_eq!(segment("", &model()), "");
+    }
+
+    #[test]
+    fn segment_keeps_unknown_runs_whole() {
+        assert_eq!(segment("itwasthexyzzyof", &model()), "it was the xyzzy of");
+    }
+
+    #[test]
+    fn add_dictionary_adds_missing_words_once() {
+        let mut model = model();
+        model.add_dictionary("Belief\nthe\nit's\n\n");
+        assert_eq!(model.counts["belief"], 1);
+        assert_eq!(model.counts["the"], 4);
+        assert!(!model.counts.contains_key("it's"));
+        assert_eq!(model.total, 23);
+        assert_eq!(segment("theepochofbelief", &model), "the epoch of belief");
+    }
+
+    #[test]
+    fn segment_text_keeps_case_and_punctuation() {
+        assert_eq!(segment_text("ItWas the BestOfTimes, itwastheworstoftimes!", &model()),
+            "It Was the Best Of Times, it was the worst of times!");
+    }
+
+    #[test]
+    fn model_text_round_trips() {
+        let model = model();
+        let read = WordModel::from_text(&model.to_text()).unwrap();
+        assert_eq!(read.to_text(), model.to_text());
+        assert_eq!(segment("itwastheworst", &read), "it was the worst");
+        assert!(WordModel::from_text("").is_err());
+        assert!(WordModel::from_text("The 3\n").is_err());
+    }
+}
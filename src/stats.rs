@@ -0,0 +1,44 @@
+/// Index of coincidence of English text.
+pub const ENGLISH_IC: f64 = 0.0667;
+
+/// Index of coincidence of uniformly random letters.
+pub const RANDOM_IC: f64 = 1.0 / 26.0;
+
+/// Counts of each letter of `text` (letters as 0..26).
+pub fn letter_counts(text: &[u8]) -> [usize; 26] {
+    let mut counts = [0; 26];
+    for x in text {
+        counts[*x as usize] += 1;
+    }
+    counts
+}
+
+/// Chance that two letters picked from `text` (letters as 0..26) are equal.
+pub fn index_of_coincidence(text: &[u8]) -> f64 {
+    let len = text.len();
+    if len < 2 {
+        return 0.0;
+    }
+    let pairs: usize = letter_counts(text).iter().map(|n| n * n.saturating_sub(1)).sum();
+    pairs as f64 / (len * (len - 1)) as f64
+}
+
+/// Average index of coincidence of the `period` columns of `text`, as seen by
+/// a periodic cipher with that key length.
+pub fn periodic_ic(text: &[u8], period: usize) -> f64 {
+    let total: f64 = (0..period)
+        .map(|column| {
+            let letters: Vec<u8> = text.iter().skip(column).step_by(period).copied().collect();
+            index_of_coincidence(&letters)
+        })
+        .sum();
+    total / period as f64
+}
+
+/// Converts `a..=z` text to letters as 0..26, skipping every other char.
+pub fn to_letters(text: &str) -> Vec<u8> {
+    text.chars()
+        .filter(|x| x.is_ascii_lowercase())
+        .map(|x| x as u8 - 97)
+        .collect()
+}
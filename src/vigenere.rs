@@ -1,174 +1,555 @@
-use std::fs;
-use std::collections::HashSet;
-use rand::thread_rng;
+use std::collections::HashMap;
+use std::time::Instant;
+use rand::Rng;
 use rand::seq::SliceRandom;
 
+use crate::cipher::Cipher;
 use crate::english::ALPHABET;
-use crate::fitness::{
-    FitnessMatrix,
-    generate_fitness_matrix_from_file,
-    compute_fitness};
+use crate::caesar;
+use crate::search::{Neighbourhood, Scorer, SearchOptions, SearchResult, Strategy, hill_climb, search};
+use crate::fitness::{FitnessMatrix, compute_fitness};
+use crate::stats::{ENGLISH_IC, RANDOM_IC, index_of_coincidence, periodic_ic, to_letters};
 
-fn generate_key(length: usize) -> String {
+/// Periodic Vigenère cipher; `key_length` is the length of generated keys.
+pub struct Vigenere {
+    pub key_length: usize,
+}
+
+/// Returns a random key of `length` letters.
+pub fn generate_key<R: Rng + ?Sized>(length: usize, rng: &mut R) -> String {
     let mut key = String::new();
-    let mut rng = thread_rng();
     for _ in 0..length {
-        let random_char = ALPHABET.choose(&mut rng).unwrap();
+        let random_char = ALPHABET.choose(rng).unwrap();
         key.push(*random_char);
     }
     key
 }
 
-fn common<F>(source: &str, key: &str, calc_idx: F) -> String
+fn common<F>(source: &str, key: &str, calc_idx: F) -> Result<String, String>
     where F: Fn(usize, usize) -> usize 
 {
+    if key.is_empty() {
+        return Err("Key must not be empty".to_string());
+    }
+    if let Some(invalid) = source.chars().chain(key.chars())
+        .find(|x| !x.is_ascii_lowercase())
+    {
+        return Err(format!("Char '{invalid}' is out of range a..=z"));
+    }
     let mut transformed = String::new();
     let text: Vec<usize> = source.chars().map(|x| x as usize - 97).collect();
     let key: Vec<usize> = key.chars().map(|x| x as usize - 97).collect();
-    for i in 0..text.len() {
+    for (i, text_char) in text.into_iter().enumerate() {
         let j = i % key.len();
-        let idx = calc_idx(text[i], key[j]);
+        let idx = calc_idx(text_char, key[j]);
         transformed.push(ALPHABET[idx]);
     }
-    transformed
+    Ok(transformed)
 }
 
-fn encrypt(plaintext: &str, key: &str) -> String {
+pub fn encrypt(plaintext: &str, key: &str) -> Result<String, String> {
     common(plaintext, key, |x, y| (x + y) % 26)
 }
 
-fn decrypt(ciphertext: &str, key: &str) -> String {
+pub fn decrypt(ciphertext: &str, key: &str) -> Result<String, String> {
     common(ciphertext, key, |x, y| (x + 26 - y) % 26)
 }
 
-fn find_key_lengths(ciphertext: &str) -> Result<Vec<usize>, &str> {
-    let mut bigram_distances: Vec<HashSet<usize>> = Vec::new();
-    let mut bigrams: Vec<&str> = Vec::new();
-    for bigram_idx in 0..ciphertext.len()-1 {
-        let bigram = &ciphertext[bigram_idx..bigram_idx+2];
-        if bigrams.contains(&bigram) {
-            continue;
+/// Beaufort cipher, `c = k - p`. It is its own inverse.
+pub fn beaufort_encrypt(plaintext: &str, key: &str) -> Result<String, String> {
+    common(plaintext, key, |x, y| (y + 26 - x) % 26)
+}
+
+pub fn beaufort_decrypt(ciphertext: &str, key: &str) -> Result<String, String> {
+    beaufort_encrypt(ciphertext, key)
+}
+
+/// Variant Beaufort, `c = p - k`: Vigenère with encryption and decryption
+/// swapped.
+pub fn variant_beaufort_encrypt(plaintext: &str, key: &str) -> Result<String, String> {
+    decrypt(plaintext, key)
+}
+
+pub fn variant_beaufort_decrypt(ciphertext: &str, key: &str) -> Result<String, String> {
+    encrypt(ciphertext, key)
+}
+
+/// Converts a Gronsfeld key of digits to the equivalent Vigenère key.
+pub fn gronsfeld_key(key: &str) -> Result<String, String> {
+    key.chars()
+        .map(|x| x.to_digit(10)
+            .map(|digit| ALPHABET[digit as usize])
+            .ok_or(format!("Char '{x}' of Gronsfeld key is not a digit")))
+        .collect()
+}
+
+/// Gronsfeld cipher: Vigenère with a key of digits shifting by 0..=9.
+pub fn gronsfeld_encrypt(plaintext: &str, key: &str) -> Result<String, String> {
+    encrypt(plaintext, &gronsfeld_key(key)?)
+}
+
+pub fn gronsfeld_decrypt(ciphertext: &str, key: &str) -> Result<String, String> {
+    decrypt(ciphertext, &gronsfeld_key(key)?)
+}
+
+/// Shortest key repeating to `key`, as keys found for a multiple of the
+/// true length repeat themselves.
+pub fn shortest_key(key: &str) -> String {
+    let len = key.len();
+    (1..len)
+        .filter(|period| len.is_multiple_of(*period))
+        .find(|period| key.as_bytes().chunks(*period).all(|x| x == &key.as_bytes()[..*period]))
+        .map_or(key.to_string(), |period| key[..period].to_string())
+}
+
+/// Negates every letter, `x` to `-x`, of a key or text.
+fn negate(text: &str) -> String {
+    text.chars()
+        .map(|x| ALPHABET[(26 - (x as usize - 97)) % 26])
+        .collect()
+}
+
+/// Longest key length [`rank_key_lengths`] considers.
+pub const MAX_KEY_LENGTH: usize = 30;
+
+/// Friedman test: the key length implied by the IC of the whole ciphertext.
+pub fn friedman_key_length(ciphertext: &str) -> f64 {
+    let text = to_letters(ciphertext);
+    let len = text.len() as f64;
+    let ic = index_of_coincidence(&text);
+    let denominator = (len - 1.0) * ic - RANDOM_IC * len + ENGLISH_IC;
+    if denominator <= 0.0 {
+        return f64::INFINITY;
+    }
+    (ENGLISH_IC - RANDOM_IC) * len / denominator
+}
+
+/// Share of the distances between repeated trigrams divisible by each key
+/// length, or `None` with too few repeats to tell.
+fn kasiski_shares(text: &[u8], max_length: usize) -> Option<Vec<f64>> {
+    let mut last_seen: HashMap<&[u8], usize> = HashMap::new();
+    let mut distances = Vec::new();
+    for (pos, trigram) in text.windows(3).enumerate() {
+        if let Some(last) = last_seen.insert(trigram, pos) {
+            distances.push(pos - last);
         }
-        bigrams.push(bigram);
+    }
+    if distances.len() < 3 {
+        return None;
+    }
+    Some((0..=max_length)
+        .map(|length| {
+            let divisible = distances.iter()
+                .filter(|distance| length > 0 && distance.is_multiple_of(length))
+                .count();
+            divisible as f64 / distances.len() as f64
+        })
+        .collect())
+}
 
-        let positions: Vec<usize> = ciphertext
-            .match_indices(bigram)
-            .map(|(bigram_idx, _)| bigram_idx)
+/// Scores every key length up to [`MAX_KEY_LENGTH`] by the average column IC,
+/// Kasiski examination of repeated trigrams and the Friedman test, and
+/// returns `(length, score)` pairs, best first. Scores are about 1 for the
+/// true length; multiples of it score lower as fewer repeats divide them.
+pub fn rank_key_lengths(ciphertext: &str) -> Vec<(usize, f64)> {
+    let text = to_letters(ciphertext);
+    // Columns need some letters for their IC to mean anything.
+    let max_length = MAX_KEY_LENGTH.min(text.len() / 10).max(1);
+    let kasiski = kasiski_shares(&text, max_length);
+    let friedman = friedman_key_length(ciphertext);
+    let mut ranked: Vec<(usize, f64)> = (1..=max_length)
+        .map(|length| {
+            let column_ic = (periodic_ic(&text, length) - RANDOM_IC) / (ENGLISH_IC - RANDOM_IC);
+            let repeats = kasiski.as_ref().map_or(0.5, |shares| shares[length]);
+            let estimate = (-((length as f64 / friedman).ln() / 0.7).powi(2)).exp();
+            let score = column_ic.clamp(0.0, 1.2)
+                * (0.5 + 0.5 * repeats)
+                * (0.8 + 0.2 * estimate);
+            (length, score)
+        })
+        .collect();
+    ranked.sort_by(|(_, s1), (_, s2)| s2.partial_cmp(s1).unwrap());
+    ranked
+}
+
+/// Searches keys of a fixed length by changing one key letter at a time.
+pub struct VigenereCracker<'a> {
+    ciphertext: Vec<u8>,
+    key_length: usize,
+    matrix: &'a FitnessMatrix,
+}
+
+impl<'a> VigenereCracker<'a> {
+    pub fn new(ciphertext: &str, key_length: usize, matrix: &'a FitnessMatrix) -> Self {
+        let ciphertext = ciphertext.chars()
+            .map(|x| x as u8 - 97)
             .collect();
-        if positions.len() < 2 {
-            continue;
+        VigenereCracker { ciphertext, key_length, matrix }
+    }
+}
+
+impl Neighbourhood for VigenereCracker<'_> {
+    type Key = String;
+
+    fn random_key<R: Rng + ?Sized>(&self, rng: &mut R) -> String {
+        generate_key(self.key_length, rng)
+    }
+
+    fn neighbour_count(&self) -> usize {
+        self.key_length * 26
+    }
+
+    fn neighbour(&self, key: &String, idx: usize) -> String {
+        let mut key: Vec<char> = key.chars().collect();
+        key[idx / 26] = ALPHABET[idx % 26];
+        key.into_iter().collect()
+    }
+}
+
+impl Scorer<String> for VigenereCracker<'_> {
+    fn score(&self, key: &String) -> f64 {
+        let key: Vec<u8> = key.chars().map(|x| x as u8 - 97).collect();
+        let plaintext: Vec<u8> = self.ciphertext.iter().enumerate()
+            .map(|(idx, x)| (x + 26 - key[idx % key.len()]) % 26)
+            .collect();
+        compute_fitness(&plaintext, self.matrix)
+    }
+}
+
+/// Default search settings for [`crack`], applied to every key length.
+pub fn search_options() -> SearchOptions {
+    SearchOptions {
+        max_restarts: 2,
+        max_hits: 1,
+        ..SearchOptions::default()
+    }
+}
+
+/// How many of the best ranked key lengths [`crack`] searches.
+pub const KEY_LENGTHS_TRIED: usize = 6;
+
+/// Searches the likeliest key lengths and keeps the best key, reporting
+/// each length tried if `options.report_every` is set.
+pub fn crack(ciphertext: &str, matrix: &FitnessMatrix, options: &SearchOptions)
+    -> Result<SearchResult<String>, &'static str>
+{
+    if ciphertext.len() < 150 {
+        return Err("Length of cipher must be at least 150");
+    }
+    if !ciphertext.chars().all(|x| x.is_ascii_lowercase()) {
+        return Err("Cipher must only contain a..=z");
+    }
+    let mut local_maximum_hit = 0;
+    let mut best: Option<SearchResult<String>> = None;
+    for (length, score) in rank_key_lengths(ciphertext).into_iter().take(KEY_LENGTHS_TRIED) {
+        if best.is_some() && options.deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            break;
+        }
+        if options.report_every != 0 {
+            eprintln!("Length: {length} (score {score:.3})");
         }
+        let cracker = VigenereCracker::new(ciphertext, length, matrix);
+        let mut result = search(&cracker, options);
+        result.key = shortest_key(&result.key);
 
-        let mut distances: HashSet<usize> = HashSet::new();
-        for pos in 0..positions.len()-1 {
-            let distance = positions[pos+1]-positions[pos];
-            for factor in 1..=distance {
-                if distance % factor == 0 {
-                    distances.insert(factor);
+        match &best {
+            Some(current) if result.fitness < current.fitness => {},
+            Some(current) if result.fitness == current.fitness => {
+                local_maximum_hit += 1;
+                if local_maximum_hit == 3 {
+                    break;
                 }
-            }
+            },
+            _ => best = Some(result),
         }
-        bigram_distances.push(distances);
     }
-    if bigram_distances.len() < 3 {
-        return Err("Not enough bigram");
+    best.ok_or("No key length to try")
+}
+
+/// Solves every column of a key of `key_length` as a Caesar shift, picking
+/// the shift with the lowest chi-squared against `expect_freq`.
+pub fn solve_columns(ciphertext: &str, key_length: usize, expect_freq: &[f64; 26])
+    -> Result<String, String>
+{
+    if key_length == 0 {
+        return Err("Key length must be at least 1".to_string());
     }
-    bigram_distances.sort_by(|a, b| b.len().cmp(&a.len()));
+    let chars: Vec<char> = ciphertext.chars().collect();
+    let mut key = String::new();
+    for column in 0..key_length {
+        let column: String = chars.iter().skip(column).step_by(key_length).collect();
+        if column.is_empty() {
+            return Err(format!("Cipher is shorter than key length {key_length}"));
+        }
+        // Caesar keys shift forward to decrypt, Vigenère keys shift back.
+        let (_, shift) = caesar::crack(&column, expect_freq)?[0];
+        key.push(ALPHABET[(26 - shift) % 26]);
+    }
+    Ok(key)
+}
 
-    let mut key_lengths: HashSet<usize> = bigram_distances[0].clone();
-    for distances in &bigram_distances[1..3] {
-        key_lengths = key_lengths
-            .intersection(&distances)
-            .map(|x| *x)
-            .collect();
+/// Hill climbs from `key` on the fitness of the decryption.
+pub fn polish(ciphertext: &str, key: String, matrix: &FitnessMatrix) -> (f64, String) {
+    let cracker = VigenereCracker::new(ciphertext, key.len(), matrix);
+    hill_climb(&cracker, key)
+}
+
+/// Deterministic alternative to [`crack`]: solves the columns of the
+/// likeliest key lengths with [`solve_columns`], optionally polishes each
+/// key with [`polish`], and keeps the fittest. Each key is reported on
+/// stderr if `report` is set.
+pub fn crack_columns(ciphertext: &str, matrix: &FitnessMatrix, polish_key: bool, report: bool)
+    -> Result<SearchResult<String>, &'static str>
+{
+    if ciphertext.len() < 20 {
+        return Err("Length of cipher must be at least 20");
+    }
+    if !ciphertext.chars().all(|x| x.is_ascii_lowercase()) {
+        return Err("Cipher must only contain a..=z");
     }
+    let mut best: Option<SearchResult<String>> = None;
+    for (length, _) in rank_key_lengths(ciphertext).into_iter().take(KEY_LENGTHS_TRIED) {
+        let key = solve_columns(ciphertext, length, matrix.unigram())
+            .map_err(|_| "Cipher is too short for its key lengths")?;
+        let (fitness, key) = if polish_key {
+            polish(ciphertext, key, matrix)
+        } else {
+            let plaintext = decrypt(ciphertext, &key).unwrap();
+            (compute_fitness(&to_letters(&plaintext), matrix), key)
+        };
+        let key = shortest_key(&key);
+        if report {
+            eprintln!("Length: {length}, key {key}, fitness {fitness:.4}");
+        }
+        // Ties go to the likelier length, multiples of it decrypt the same.
+        if best.as_ref().is_none_or(|current| fitness > current.fitness) {
+            best = Some(SearchResult { fitness, key, restarts: 0, strategy: Strategy::HillClimb });
+        }
+    }
+    best.ok_or("No key length to try")
+}
 
-    let mut key_lengths: Vec<usize> = key_lengths.into_iter().collect();
-    key_lengths.sort();
-    Ok(key_lengths)
+/// The Vigenère relatives [`crack_variants`] tells apart.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Variant {
+    Beaufort,
+    VariantBeaufort,
+    Gronsfeld,
 }
 
-fn hill_climb(ciphertext: &str, key: &str, matrix: &FitnessMatrix) -> (f64, String) {
-    let mut plaintext: Vec<u8> = decrypt(ciphertext, key)
-        .chars()
-        .map(|x| x as u8 - 97)
-        .collect();
-    let mut key: Vec<char> = key.chars().collect();
+impl Variant {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Variant::Beaufort => "beaufort",
+            Variant::VariantBeaufort => "variant-beaufort",
+            Variant::Gronsfeld => "gronsfeld",
+        }
+    }
+}
 
-    let ciphertext: Vec<u8> = ciphertext.chars()
-        .map(|x| x as u8 - 97)
-        .collect();
-        
-    let mut current = compute_fitness(&plaintext, matrix);
-    loop {
-        let mut better_key = false;
-        for key_idx in 0..key.len() {
-            for alphabet_char in ALPHABET {
-                let alphabet_bin = alphabet_char as u8 - 97;
-                for idx in (key_idx..plaintext.len()).step_by(key.len()) {
-                    plaintext[idx] = (ciphertext[idx]+26-alphabet_bin) % 26;
-                }
+/// Cracks `ciphertext` as Beaufort, variant Beaufort and Gronsfeld with
+/// `crack_vigenere`, any Vigenère cracker such as [`crack`] or
+/// [`crack_columns`], and returns the variant whose plaintext is fittest,
+/// with its key written the way that variant writes keys.
+///
+/// Variant Beaufort is Vigenère with the key negated, so a Vigenère
+/// ciphertext is reported as variant Beaufort. Gronsfeld is reported when
+/// the key only shifts by 0..=9.
+pub fn crack_variants<F>(ciphertext: &str, crack_vigenere: F)
+    -> Result<(Variant, SearchResult<String>), &'static str>
+    where F: Fn(&str) -> Result<SearchResult<String>, &'static str>
+{
+    // c = p - k is Vigenère with key -k; c = k - p turns into it by
+    // negating the ciphertext.
+    let mut shifted = crack_vigenere(ciphertext)?;
+    let mut reflected = crack_vigenere(&negate(ciphertext))?;
+    reflected.key = negate(&reflected.key);
+    if reflected.fitness > shifted.fitness {
+        return Ok((Variant::Beaufort, reflected));
+    }
+    if shifted.key.chars().all(|x| x <= 'j') {
+        shifted.key = shifted.key.chars()
+            .map(|x| char::from_digit(x as u32 - 97, 10).unwrap())
+            .collect();
+        return Ok((Variant::Gronsfeld, shifted));
+    }
+    shifted.key = negate(&shifted.key);
+    Ok((Variant::VariantBeaufort, shifted))
+}
 
-                let proposal = compute_fitness(&plaintext, matrix);
-                if proposal > current {
-                    key[key_idx] = alphabet_char;
-                    current = proposal;
-                    better_key = true;
-                } else {
-                    let key_bin = key[key_idx] as u8 - 97;
-                    for idx in (key_idx..plaintext.len()).step_by(key.len()) {
-                        plaintext[idx] = (ciphertext[idx]+26-key_bin) % 26;
-                    }
-                }
-            }
+impl Cipher for Vigenere {
+    type Key = String;
+
+    fn name(&self) -> &'static str {
+        "vigenere"
+    }
+
+    fn encrypt(&self, plaintext: &str, key: &String) -> Result<String, String> {
+        encrypt(plaintext, key)
+    }
+
+    fn decrypt(&self, ciphertext: &str, key: &String) -> Result<String, String> {
+        decrypt(ciphertext, key)
+    }
+
+    fn parse_key(&self, key: &str) -> Result<String, String> {
+        let key = key.trim();
+        if key.is_empty() || !key.chars().all(|x| x.is_ascii_lowercase()) {
+            return Err(format!("Key '{key}' must be letters a..=z"));
         }
-        if !better_key { break; }
+        Ok(key.to_string())
+    }
+
+    fn format_key(&self, key: &String) -> String {
+        key.clone()
+    }
+
+    fn generate_key<R: Rng + ?Sized>(&self, rng: &mut R) -> String {
+        generate_key(self.key_length, rng)
     }
-    (current, key.iter().collect::<String>())
 }
 
-fn crack(ciphertext: &str) -> (f64, String) {
-    if ciphertext.len() < 150 {
-        panic!("Ciphertext minium len is 150");
-    }
-    let matrix = generate_fitness_matrix_from_file();
-    let key_lengths: Vec<usize> = if let Ok(x) = find_key_lengths(ciphertext) {
-        x
-    } else {
-        (1..ciphertext.len()).collect()
-    };
-    
-    let mut local_maximum = -1000.0;
-    let mut local_maximum_hit = 0;
-    let mut best_key: String = String::new();
-    for length in key_lengths {
-        println!("Length: {length}");
-        let new_key = generate_key(length);
-        let (fitness, key) = hill_climb(&ciphertext, &new_key, &matrix);
-
-        if fitness > local_maximum {
-            local_maximum = fitness;
-            best_key = key;
-        } else if fitness == local_maximum {
-            local_maximum_hit += 1;
-            if local_maximum_hit == 3 {
-                break;
-            }
+/// Beaufort cipher, see [`beaufort_encrypt`].
+pub struct Beaufort {
+    pub key_length: usize,
+}
+
+impl Cipher for Beaufort {
+    type Key = String;
+
+    fn name(&self) -> &'static str {
+        "beaufort"
+    }
+
+    fn encrypt(&self, plaintext: &str, key: &String) -> Result<String, String> {
+        beaufort_encrypt(plaintext, key)
+    }
+
+    fn decrypt(&self, ciphertext: &str, key: &String) -> Result<String, String> {
+        beaufort_decrypt(ciphertext, key)
+    }
+
+    fn parse_key(&self, key: &str) -> Result<String, String> {
+        Vigenere { key_length: self.key_length }.parse_key(key)
+    }
+
+    fn format_key(&self, key: &String) -> String {
+        key.clone()
+    }
+
+    fn generate_key<R: Rng + ?Sized>(&self, rng: &mut R) -> String {
+        generate_key(self.key_length, rng)
+    }
+}
+
+/// Variant Beaufort cipher, see [`variant_beaufort_encrypt`].
+pub struct VariantBeaufort {
+    pub key_length: usize,
+}
+
+impl Cipher for VariantBeaufort {
+    type Key = String;
+
+    fn name(&self) -> &'static str {
+        "variant-beaufort"
+    }
+
+    fn encrypt(&self, plaintext: &str, key: &String) -> Result<String, String> {
+        variant_beaufort_encrypt(plaintext, key)
+    }
+
+    fn decrypt(&self, ciphertext: &str, key: &String) -> Result<String, String> {
+        variant_beaufort_decrypt(ciphertext, key)
+    }
+
+    fn parse_key(&self, key: &str) -> Result<String, String> {
+        Vigenere { key_length: self.key_length }.parse_key(key)
+    }
+
+    fn format_key(&self, key: &String) -> String {
+        key.clone()
+    }
+
+    fn generate_key<R: Rng + ?Sized>(&self, rng: &mut R) -> String {
+        generate_key(self.key_length, rng)
+    }
+}
+
+/// Gronsfeld cipher, see [`gronsfeld_encrypt`].
+pub struct Gronsfeld {
+    pub key_length: usize,
+}
+
+impl Cipher for Gronsfeld {
+    type Key = String;
+
+    fn name(&self) -> &'static str {
+        "gronsfeld"
+    }
+
+    fn encrypt(&self, plaintext: &str, key: &String) -> Result<String, String> {
+        gronsfeld_encrypt(plaintext, key)
+    }
+
+    fn decrypt(&self, ciphertext: &str, key: &String) -> Result<String, String> {
+        gronsfeld_decrypt(ciphertext, key)
+    }
+
+    fn parse_key(&self, key: &str) -> Result<String, String> {
+        let key = key.trim();
+        if key.is_empty() || !key.chars().all(|x| x.is_ascii_digit()) {
+            return Err(format!("Key '{key}' must be digits 0..=9"));
         }
+        Ok(key.to_string())
+    }
+
+    fn format_key(&self, key: &String) -> String {
+        key.clone()
+    }
+
+    fn generate_key<R: Rng + ?Sized>(&self, rng: &mut R) -> String {
+        (0..self.key_length)
+            .map(|_| char::from_digit(rng.gen_range(0..10), 10).unwrap())
+            .collect()
     }
-    (local_maximum, best_key)
 }
 
-pub fn main() {
-    let file_content = fs::read_to_string("plaintext.txt").unwrap();
-    let plaintext = file_content.trim().replace(" ", "");
-    let key = "helloworld";
-    let ciphertext = encrypt(&plaintext, &key);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::normalise::Normaliser;
 
-    let (fitness, best_key) = crack(&ciphertext);
-    let decrypted = decrypt(&ciphertext, &best_key);
-    println!("Key: {}", key);
-    println!("Guess key: {}", best_key);
-    println!("Fitness: {}", fitness);
-    println!("Decrypted: {}", decrypted);
+    const CORPUS: &str = "It is a truth universally acknowledged, that a single man in \
+        possession of a good fortune, must be in want of a wife. However little known the \
+        feelings or views of such a man may be on his first entering a neighbourhood, this \
+        truth is so well fixed in the minds of the surrounding families, that he is \
+        considered the rightful property of some one or other of their daughters. Call me \
+        Ishmael. Some years ago, never mind how long precisely, having little or no money in \
+        my purse, and nothing particular to interest me on shore, I thought I would sail \
+        about a little and see the watery part of the world.";
+
+    const TEXT: &str = "itwasthebestoftimesitwastheworstoftimesitwastheageofwisdomitwasthe\
+        ageoffoolishnessitwastheepochofbeliefitwastheepochofincredulityitwastheseasonoflight\
+        itwastheseasonofdarknessitwasthespringofhopeitwasthewinterofdespairwehadeverything\
+        beforeuswehadnothingbeforeuswewereallgoingdirecttoheavenwewereallgoingdirecttheother\
+        way";
+
+    #[test]
+    fn crack_variants_tells_the_variants_apart() {
+        let corpus = Normaliser::default().normalise(CORPUS);
+        let matrix = FitnessMatrix::from_corpus(&corpus, 2, "en").unwrap();
+        let crack = |ciphertext: &str| crack_columns(ciphertext, &matrix, false, false);
+        let cases = [
+            (beaufort_encrypt(TEXT, "lemon").unwrap(), Variant::Beaufort, "lemon"),
+            (variant_beaufort_encrypt(TEXT, "lemon").unwrap(), Variant::VariantBeaufort,
+                "lemon"),
+            (gronsfeld_encrypt(TEXT, "31415").unwrap(), Variant::Gronsfeld, "31415"),
+            // Vigenère with key k is variant Beaufort with key -k.
+            (encrypt(TEXT, "lemon").unwrap(), Variant::VariantBeaufort, "pwomn"),
+        ];
+        for (ciphertext, variant, key) in cases {
+            let (found, result) = crack_variants(&ciphertext, crack).unwrap();
+            assert_eq!((found, result.key.as_str()), (variant, key));
+        }
+    }
 }